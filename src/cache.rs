@@ -0,0 +1,51 @@
+/// A map that only keeps the most recently used entries, up to a fixed number of them
+pub(crate) struct LruCache<K, V> {
+    capacity: usize,
+    /// The entries in the order they have been used in, the most recently used one last
+    entries: Vec<(K, V)>,
+}
+
+impl<K: PartialEq, V> LruCache<K, V> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity,
+            entries: Vec::with_capacity(capacity),
+        }
+    }
+    /// Looks up the entry and marks it as the most recently used one
+    pub(crate) fn get(&mut self, key: &K) -> Option<&mut V> {
+        let pos = self.entries.iter().position(|(k, _v)| k == key)?;
+        let entry = self.entries.remove(pos);
+        self.entries.push(entry);
+        self.entries.last_mut().map(|(_k, v)| v)
+    }
+    /// Looks up the entry without marking it as used
+    pub(crate) fn peek(&self, key: &K) -> Option<&V> {
+        self.entries
+            .iter()
+            .find(|(k, _v)| k == key)
+            .map(|(_k, v)| v)
+    }
+    pub(crate) fn contains(&self, key: &K) -> bool {
+        self.peek(key).is_some()
+    }
+    /// Inserts the entry as the most recently used one, evicting the least recently used
+    /// entry if the cache is full
+    pub(crate) fn insert(&mut self, key: K, value: V) {
+        self.entries.retain(|(k, _v)| *k != key);
+        if self.entries.len() >= self.capacity {
+            self.entries.remove(0);
+        }
+        self.entries.push((key, value));
+    }
+    /// All values, without marking them as used
+    pub(crate) fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.entries.iter_mut().map(|(_k, v)| v)
+    }
+    pub(crate) fn remove(&mut self, key: &K) {
+        self.entries.retain(|(k, _v)| k != key);
+    }
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
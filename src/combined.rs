@@ -0,0 +1,196 @@
+use std::ops::Range;
+
+use gix::diff::blob::sources::byte_lines_with_terminator;
+
+use crate::{model::DiffOptions, whitespace::line_changes};
+
+/// A line of the combined diff, either of the merge result or one that only parents have
+struct CombinedLine<'a> {
+    text: &'a [u8],
+    /// For each parent, whether the result added the line (`+`), removed it (`-`),
+    /// or whether it is unchanged (` `)
+    markers: Vec<u8>,
+}
+
+impl CombinedLine<'_> {
+    fn is_change(&self) -> bool {
+        self.markers.iter().any(|m| *m != b' ')
+    }
+    fn in_result(&self) -> bool {
+        !self.markers.contains(&b'-')
+    }
+    fn in_parent(&self, parent: usize) -> bool {
+        match self.markers[parent] {
+            b'-' => true,
+            b'+' => false,
+            _ => self.in_result(),
+        }
+    }
+}
+
+/// Computes the dense combined diff of a merge result against its parents, like
+/// `git show --cc` does.
+///
+/// Only hunks where the result differs from all parents are kept, i.e. the ones with
+/// conflicts or where the merge made changes of its own.
+pub(crate) fn combined_diff(parents: &[&[u8]], result: &[u8], opts: &DiffOptions) -> String {
+    let lines = combined_lines(parents, result, opts);
+
+    // Hunks where the result equals one of the parents are not interesting
+    let mut kept = Vec::new();
+    let mut idx = 0;
+    while idx < lines.len() {
+        if !lines[idx].is_change() {
+            idx += 1;
+            continue;
+        }
+        let start = idx;
+        while idx < lines.len() && lines[idx].is_change() {
+            idx += 1;
+        }
+        let hunk = &lines[start..idx];
+        let differs_from_all =
+            (0..parents.len()).all(|parent| hunk.iter().any(|line| line.markers[parent] != b' '));
+        if differs_from_all {
+            kept.push(start..idx);
+        }
+    }
+
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for hunk in kept {
+        let context = opts.context as usize;
+        let range = hunk.start.saturating_sub(context)..(hunk.end + context).min(lines.len());
+        match ranges.last_mut() {
+            Some(last) if last.end >= range.start => last.end = range.end,
+            _ => ranges.push(range),
+        }
+    }
+
+    let mut out = String::new();
+    for range in ranges {
+        write_hunk(&mut out, &lines, range, parents.len());
+    }
+    out
+}
+
+/// Lines of the result, with the lines that parents lost in front of the line they were
+/// removed before
+fn combined_lines<'a>(
+    parents: &[&'a [u8]],
+    result: &'a [u8],
+    opts: &DiffOptions,
+) -> Vec<CombinedLine<'a>> {
+    let result_lines = byte_lines_with_terminator(result).collect::<Vec<_>>();
+    let mut added = vec![vec![false; result_lines.len()]; parents.len()];
+    // For every position in the result, the lines that parents had there instead
+    let mut removed: Vec<Vec<CombinedLine<'a>>> =
+        (0..=result_lines.len()).map(|_| Vec::new()).collect();
+    for (parent, parent_data) in parents.iter().enumerate() {
+        let parent_lines = byte_lines_with_terminator(parent_data).collect::<Vec<_>>();
+        for (before, after) in line_changes(parent_data, result, opts) {
+            for line in after.clone() {
+                added[parent][line as usize] = true;
+            }
+            let at = &mut removed[after.start as usize];
+            // Lines that several parents had are shown only once
+            let mut search_from = 0;
+            for text in &parent_lines[before.start as usize..before.end as usize] {
+                let text = *text;
+                let found = at[search_from..]
+                    .iter()
+                    .position(|line| line.text == text && line.markers[parent] == b' ');
+                match found {
+                    Some(pos) => {
+                        at[search_from + pos].markers[parent] = b'-';
+                        search_from += pos + 1;
+                    }
+                    None => {
+                        let mut markers = vec![b' '; parents.len()];
+                        markers[parent] = b'-';
+                        at.push(CombinedLine { text, markers });
+                        search_from = at.len();
+                    }
+                }
+            }
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut removed = removed.into_iter();
+    for (idx, text) in result_lines.into_iter().enumerate() {
+        lines.extend(removed.next().unwrap_or_default());
+        let markers = added
+            .iter()
+            .map(|added| if added[idx] { b'+' } else { b' ' })
+            .collect();
+        lines.push(CombinedLine { text, markers });
+    }
+    lines.extend(removed.flatten());
+    lines
+}
+
+fn write_hunk(out: &mut String, lines: &[CombinedLine<'_>], range: Range<usize>, parents: usize) {
+    let position = |count: &dyn Fn(&CombinedLine<'_>) -> bool| {
+        let start = lines[..range.start].iter().filter(|l| count(l)).count();
+        let len = lines[range.clone()].iter().filter(|l| count(l)).count();
+        format!("{},{len}", start + 1)
+    };
+    let marker = "@".repeat(parents + 1);
+    out.push_str(&marker);
+    for parent in 0..parents {
+        out.push_str(&format!(" -{}", position(&|l| l.in_parent(parent))));
+    }
+    out.push_str(&format!(" +{} {marker}\n", position(&|l| l.in_result())));
+    for line in &lines[range] {
+        let text = String::from_utf8_lossy(line.text);
+        out.push_str(&String::from_utf8_lossy(&line.markers));
+        out.push_str(text.strip_suffix('\n').unwrap_or(&text));
+        out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(context: u32) -> DiffOptions {
+        DiffOptions {
+            context,
+            ..DiffOptions::default()
+        }
+    }
+
+    #[test]
+    fn hunks_taken_from_a_parent_are_dropped() {
+        let parents: [&[u8]; 2] = [b"a\nb\nc\n", b"a\nB\nc\n"];
+        assert_eq!(combined_diff(&parents, b"a\nB\nc\n", &opts(3)), "");
+        assert_eq!(combined_diff(&parents, b"a\nb\nc\n", &opts(3)), "");
+    }
+
+    #[test]
+    fn hunks_differing_from_all_parents_are_kept() {
+        let parents: [&[u8]; 2] = [b"a\nb\nc\n", b"a\nB\nc\n"];
+        assert_eq!(
+            combined_diff(&parents, b"a\nX\nc\n", &opts(0)),
+            "@@@ -2,1 -2,1 +2,1 @@@\n- b\n -B\n++X\n"
+        );
+        assert_eq!(
+            combined_diff(&parents, b"a\nX\nc\n", &opts(1)),
+            "@@@ -1,3 -1,3 +1,3 @@@\n  a\n- b\n -B\n++X\n  c\n"
+        );
+    }
+
+    #[test]
+    fn only_the_merge_changes_remain() {
+        let parents: [&[u8]; 2] = [b"1\n2\n3\n4\n5\n6\n7\n", b"1\ntwo\n3\n4\n5\n6\n7\n"];
+        let result = b"1\ntwo\n3\n4\n5\nsix\n7\n";
+        assert_eq!(
+            combined_diff(&parents, result, &opts(0)),
+            "@@@ -6,1 -6,1 +6,1 @@@\n--6\n++six\n"
+        );
+        assert_eq!(
+            combined_diff(&parents, result, &opts(1)),
+            "@@@ -5,3 -5,3 +5,3 @@@\n  5\n--6\n++six\n  7\n"
+        );
+    }
+}
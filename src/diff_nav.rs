@@ -0,0 +1,75 @@
+use crate::{State, draw::split_diff_area};
+
+impl State {
+    /// Scrolls the diff area to the next or the previous hunk
+    pub(crate) fn jump_to_hunk(&mut self, next: bool) {
+        let sidx = self.diff_scroll_idx;
+        let Some(rendered_diff) = self.rendered_diff() else {
+            return;
+        };
+        let hunk_starts = rendered_diff.hunk_starts();
+        let target = if next {
+            hunk_starts.into_iter().find(|start| *start > sidx)
+        } else {
+            hunk_starts.into_iter().rev().find(|start| *start < sidx)
+        };
+        match target {
+            Some(target) => self.diff_scroll_idx = target,
+            None if next => self.status = Some("No more hunks below".to_owned()),
+            None => self.status = Some("No more hunks above".to_owned()),
+        }
+    }
+    /// Scrolls the diff area so that its last line is at the bottom
+    pub(crate) fn scroll_diff_to_end(&mut self) {
+        let diff_h = split_diff_area(self.last_diff_area)[0]
+            .height
+            .saturating_sub(2) as usize;
+        let Some(rendered_diff) = self.rendered_diff() else {
+            return;
+        };
+        let len = rendered_diff.lines().count();
+        self.diff_scroll_idx = len.saturating_sub(diff_h);
+    }
+    /// Folds or unfolds the file at the top of the diff area.
+    ///
+    /// Folding is remembered for the path, so that it also applies to other commits.
+    pub(crate) fn toggle_fold(&mut self) {
+        let sidx = self.diff_scroll_idx;
+        let Some(rendered_diff) = self.rendered_diff() else {
+            return;
+        };
+        let Some(file_idx) = rendered_diff.file_at(sidx) else {
+            return;
+        };
+        let file_start = rendered_diff.file_starts()[file_idx];
+        let Some(path) = rendered_diff.files[file_idx].path.clone() else {
+            return;
+        };
+        if !self.folded_paths.remove(&path) {
+            self.folded_paths.insert(path);
+        }
+        // Keep the file at the top, its hunks would be gone from under the view
+        self.diff_scroll_idx = file_start;
+        self.invalidate_layout();
+    }
+    /// Folds all files of the diff area, or unfolds them if they are all folded already
+    pub(crate) fn toggle_fold_all(&mut self) {
+        let Some(rendered_diff) = self.rendered_diff() else {
+            return;
+        };
+        let paths = rendered_diff
+            .files
+            .iter()
+            .filter_map(|file| file.path.clone())
+            .collect::<Vec<_>>();
+        if paths.iter().all(|path| self.folded_paths.contains(path)) {
+            for path in &paths {
+                self.folded_paths.remove(path);
+            }
+        } else {
+            self.folded_paths.extend(paths);
+        }
+        self.diff_scroll_idx = 0;
+        self.invalidate_layout();
+    }
+}
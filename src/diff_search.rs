@@ -0,0 +1,199 @@
+use std::ops::Range;
+
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+
+use crate::{State, rendered_diff::RenderedDiff, search::SearchDirection, search::match_style};
+
+/// Lines to keep visible above a match that the diff area is scrolled to
+const MATCH_CONTEXT: usize = 3;
+
+/// A search through the text shown in the diff area
+pub(crate) struct DiffSearch {
+    /// The needle as the user entered it
+    pub(crate) query: String,
+    /// The query in lowercase, as the search ignores case
+    needle: String,
+    /// Line and byte range of the selected match
+    current: Option<(usize, Range<usize>)>,
+}
+
+impl DiffSearch {
+    /// The byte ranges of the matches within the line
+    fn line_matches(&self, line: &Line<'_>) -> Vec<Range<usize>> {
+        let text = line_text(line);
+        if self.needle.is_empty() {
+            return Vec::new();
+        }
+        let lower = text.to_lowercase();
+        // Lowercasing can change byte lengths of some characters, in that case we can't map
+        // the positions back, so fall back to a search that respects case
+        let (haystack, needle) = if lower.len() == text.len() {
+            (lower.as_str(), self.needle.as_str())
+        } else {
+            (text.as_str(), self.query.as_str())
+        };
+        let mut ranges = Vec::new();
+        let mut pos = 0;
+        for (start, _) in haystack.match_indices(needle) {
+            if start >= pos {
+                pos = start + needle.len();
+                ranges.push(start..pos);
+            }
+        }
+        ranges
+    }
+    /// All matches in the rendered diff, as line index and byte range within the line
+    fn matches(&self, rendered_diff: &RenderedDiff) -> Vec<(usize, Range<usize>)> {
+        rendered_diff
+            .lines()
+            .enumerate()
+            .flat_map(|(idx, line)| {
+                self.line_matches(line)
+                    .into_iter()
+                    .map(move |range| (idx, range))
+            })
+            .collect()
+    }
+    /// Highlights the matches in the lines of the diff area, the first of which has the given
+    /// index
+    pub(crate) fn highlight(&self, lines: &mut [Line<'static>], first_idx: usize) {
+        for (idx, line) in (first_idx..).zip(lines.iter_mut()) {
+            let ranges = self.line_matches(line);
+            if ranges.is_empty() {
+                continue;
+            }
+            let current = self
+                .current
+                .as_ref()
+                .filter(|(current_idx, _range)| *current_idx == idx)
+                .map(|(_idx, range)| range);
+            *line = highlight_ranges(line, &ranges, current);
+        }
+    }
+}
+
+fn line_text(line: &Line<'_>) -> String {
+    line.spans
+        .iter()
+        .map(|span| span.content.as_ref())
+        .collect()
+}
+
+fn current_match_style() -> Style {
+    Style::default().fg(Color::Black).bg(Color::LightRed)
+}
+
+/// Splits the spans of the line at the boundaries of the ranges, and highlights the ranges
+fn highlight_ranges(
+    line: &Line<'static>,
+    ranges: &[Range<usize>],
+    current: Option<&Range<usize>>,
+) -> Line<'static> {
+    let mut spans = Vec::new();
+    let mut span_start = 0;
+    for span in &line.spans {
+        let content = span.content.as_ref();
+        let span_end = span_start + content.len();
+        // Cut the span at every range boundary that falls inside of it
+        let mut cuts = ranges
+            .iter()
+            .flat_map(|range| [range.start, range.end])
+            .filter(|pos| *pos > span_start && *pos < span_end)
+            .map(|pos| pos - span_start)
+            .filter(|pos| content.is_char_boundary(*pos))
+            .collect::<Vec<_>>();
+        cuts.push(content.len());
+        let mut piece_start = 0;
+        for cut in cuts {
+            if cut <= piece_start {
+                continue;
+            }
+            let pos = span_start + piece_start;
+            let style = if current.is_some_and(|range| range.contains(&pos)) {
+                span.style.patch(current_match_style())
+            } else if ranges.iter().any(|range| range.contains(&pos)) {
+                span.style.patch(match_style())
+            } else {
+                span.style
+            };
+            spans.push(Span::styled(content[piece_start..cut].to_owned(), style));
+            piece_start = cut;
+        }
+        span_start = span_end;
+    }
+    Line {
+        spans,
+        style: line.style,
+        alignment: line.alignment,
+    }
+}
+
+impl State {
+    pub(crate) fn start_diff_search(&mut self, query: String) {
+        if query.is_empty() {
+            self.diff_search = None;
+            return;
+        }
+        self.diff_search = Some(DiffSearch {
+            needle: query.to_lowercase(),
+            query,
+            current: None,
+        });
+        self.find_diff_match(SearchDirection::Forward);
+    }
+    /// Selects the next match in the diff area in the given direction and scrolls to it,
+    /// wrapping around at the ends
+    pub(crate) fn find_diff_match(&mut self, direction: SearchDirection) {
+        let Some(search) = self.diff_search.take() else {
+            return;
+        };
+        let matches = self
+            .rendered_diff()
+            .map(|rendered_diff| search.matches(rendered_diff))
+            .unwrap_or_default();
+        let search = self.diff_search.insert(search);
+        if matches.is_empty() {
+            search.current = None;
+            self.status = Some(format!("No matches for '{}' in the diff", search.query));
+            return;
+        }
+        // Without a selected match in this diff, continue from the top of the view
+        let current_pos = search
+            .current
+            .as_ref()
+            .and_then(|current| matches.iter().position(|m| m == current));
+        let scroll_line = self.diff_scroll_idx + MATCH_CONTEXT;
+        let pos = match (direction, current_pos) {
+            (SearchDirection::Forward, Some(pos)) => (pos + 1) % matches.len(),
+            (SearchDirection::Backward, Some(pos)) => {
+                pos.checked_sub(1).unwrap_or(matches.len() - 1)
+            }
+            (SearchDirection::Forward, None) => matches
+                .iter()
+                .position(|(line, _range)| *line >= self.diff_scroll_idx)
+                .unwrap_or(0),
+            (SearchDirection::Backward, None) => matches
+                .iter()
+                .rposition(|(line, _range)| *line < scroll_line)
+                .unwrap_or(matches.len() - 1),
+        };
+        let wrapped = match (direction, current_pos) {
+            (SearchDirection::Forward, Some(current_pos)) => pos < current_pos,
+            (SearchDirection::Backward, Some(current_pos)) => pos > current_pos,
+            (_, None) => false,
+        };
+        let (line, _range) = &matches[pos];
+        self.diff_scroll_idx = line.saturating_sub(MATCH_CONTEXT);
+        self.status = Some(format!(
+            "match {}/{} for '{}'{}",
+            pos + 1,
+            matches.len(),
+            search.query,
+            if wrapped { ", search wrapped" } else { "" }
+        ));
+        search.current = Some(matches[pos].clone());
+    }
+}
@@ -1,47 +1,155 @@
+use std::ops::Range;
+
 use ratatui::{
     Frame,
     layout::{Constraint, Layout, Rect},
-    style::{Style, Stylize},
+    style::{Color, Style, Stylize},
     text::{Line, Span, Text},
     widgets::{Block, Paragraph, Scrollbar, ScrollbarState, Wrap},
 };
 
-use crate::model::{CommitDetail, Detail, Diff, FileModificationKind};
+use crate::{
+    file_list::{file_cursor_idx, listed_files},
+    graph::GraphRow,
+    model::{CommitDetail, Detail, DetailParams, Diff, DiffStat, FileModificationKind},
+    rendered_diff::{RenderedDiff, RenderedFile, parse_diff},
+    search::{highlight_spans, match_style},
+    syntax::{Highlighter, MAX_HIGHLIGHTED_LINES},
+    word_diff::WordDiff,
+};
 
 use super::State;
 
-#[derive(Clone)]
-pub(crate) struct RenderedDiff {
-    pub(crate) texts: Vec<(Line<'static>, Text<'static>)>,
+/// Columns of the bar of the file with the most changes in the diffstat
+const DIFFSTAT_BAR_WIDTH: usize = 10;
+
+/// The numbers of added and removed lines of a file, and a bar of `+` and `-` like
+/// `git diff --stat` shows, that is scaled down if the file with the most changes doesn't fit
+fn diffstat_spans(stat: DiffStat, max_changes: usize) -> Vec<Span<'static>> {
+    let changes = stat.insertions + stat.deletions;
+    if changes == 0 {
+        return Vec::new();
+    }
+    let bar = if max_changes > DIFFSTAT_BAR_WIDTH {
+        // Every changed file gets at least one column
+        (changes * DIFFSTAT_BAR_WIDTH).div_ceil(max_changes)
+    } else {
+        changes
+    };
+    let mut plus = (stat.insertions * bar + changes / 2) / changes;
+    if stat.insertions > 0 && plus == 0 {
+        plus = 1;
+    } else if stat.deletions > 0 && plus == bar {
+        plus = bar - 1;
+    }
+    vec![
+        Span::styled(format!(" +{}", stat.insertions), Style::default().green()),
+        Span::styled(format!(" -{} ", stat.deletions), Style::default().red()),
+        Span::styled("+".repeat(plus), Style::default().green()),
+        Span::styled("-".repeat(bar - plus), Style::default().red()),
+    ]
+}
+
+/// The totals of the diffstat, like `git diff --shortstat`
+fn diffstat_summary(diff: &Diff) -> String {
+    let plural = |n: usize| if n == 1 { "" } else { "s" };
+    // Modified files without changed lines are left out, like when only whitespace changed
+    // and that is ignored
+    let files = diff
+        .files
+        .iter()
+        .filter(|(kind, _path, _diff, stat)| {
+            !matches!(kind, FileModificationKind::Modification)
+                || stat.insertions + stat.deletions > 0
+        })
+        .count();
+    let stat = diff.stat();
+    let (insertions, deletions) = (stat.insertions, stat.deletions);
+    let mut summary = format!("{files} file{} changed", plural(files));
+    // Like git, zero counts are left out unless there are no changed lines at all
+    if insertions > 0 || deletions == 0 {
+        summary += &format!(", {insertions} insertion{}(+)", plural(insertions));
+    }
+    if deletions > 0 || insertions == 0 {
+        summary += &format!(", {deletions} deletion{}(-)", plural(deletions));
+    }
+    summary
+}
+
+/// Splits the diff area into the area with the text of the diff, and the file list
+pub(crate) fn split_diff_area(diff_area: Rect) -> [Rect; 2] {
+    Layout::horizontal([Constraint::Fill(3), Constraint::Fill(1)]).areas(diff_area)
+}
+
+/// The number of columns available to the text of the diff, which has a border on both
+/// sides and the scrollbar on the right
+pub(crate) fn diff_text_width(diff_area: Rect) -> u16 {
+    split_diff_area(diff_area)[0].width.saturating_sub(3)
 }
 
+/// The commit, author and time columns of the log area
+type LogColumns<'a> = (Vec<Line<'a>>, Vec<Line<'a>>, Vec<Line<'a>>);
+
+/// Deleted files with more lines than this are shown as a single line if requested
+const COLLAPSED_DELETION_LINES: usize = 20;
+
 impl State {
     pub(crate) fn draw(&mut self, frame: &mut Frame) -> Result<(), std::io::Error> {
         let area = frame.area();
 
+        let has_status_line =
+            self.prompt.is_some() || self.status.is_some() || self.search_running();
+        let [area, status_area] = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(has_status_line as u16),
+        ])
+        .areas(area);
+
+        let log_constraint = match self.log_height {
+            Some(log_height) => Constraint::Length(log_height),
+            None => Constraint::Fill(1),
+        };
         let [log_area, diff_area] =
-            Layout::vertical([Constraint::Fill(1), Constraint::Fill(2)]).areas(area);
+            Layout::vertical([log_constraint, Constraint::Fill(2)]).areas(area);
 
-        // We allocate a bit more commits here than needed but this is ok
-        if self.wanted_commit_list_count != log_area.height as usize + self.commits_scroll_idx {
-            self.wanted_commit_list_count = log_area.height as usize + self.commits_scroll_idx;
-            self.invalidate_caches();
-        }
+        // We allocate a bit more commits here than needed but this is ok.
+        // Never shrink the list, as searches might have extended it.
+        self.wanted_commit_list_count = self
+            .wanted_commit_list_count
+            .max(log_area.height as usize + self.commits_scroll_idx);
 
         self.last_log_area = log_area;
         self.last_diff_area = diff_area;
 
         self.draw_log_area(frame, log_area)?;
         self.draw_selected_commit_area(frame, diff_area)?;
+        self.draw_status_line(frame, status_area);
 
         Ok(())
     }
+    fn draw_status_line(&self, frame: &mut Frame, status_area: Rect) {
+        if let Some(prompt) = &self.prompt {
+            let text = format!("{}{}", prompt.prefix(), prompt.input);
+            let cursor_x = status_area.x + text.chars().count() as u16;
+            frame.render_widget(Paragraph::new(text), status_area);
+            frame.set_cursor_position((cursor_x, status_area.y));
+        } else if let Some(status) = &self.status {
+            frame.render_widget(Paragraph::new(status.as_str()), status_area);
+        } else if let Some(progress) = self.search_progress() {
+            frame.render_widget(Paragraph::new(progress), status_area);
+        }
+    }
     fn draw_log_area(&mut self, frame: &mut Frame, log_area: Rect) -> Result<(), std::io::Error> {
-        let commits_scroll_idx = self.commits_scroll_idx as u16;
-
-        let (lines, authors, times) = self
-            .commits_authors_times_lines()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        let commit_order = self.commit_order;
+
+        // Only build the lines that are visible, the log might be long
+        let visible_rows =
+            self.commits_scroll_idx..self.commits_scroll_idx + log_area.height as usize;
+        let loading = self.commits_shallow_loading();
+        let (mut lines, authors, times) = self.commits_authors_times_lines(visible_rows);
+        if lines.is_empty() && loading {
+            lines.push(Line::from("Loading…"));
+        }
 
         let [commit_area, author_area, times_area] = Layout::horizontal([
             Constraint::Fill(2),
@@ -50,70 +158,244 @@ impl State {
         ])
         .areas(log_area);
 
-        let paragraph = Paragraph::new(lines).scroll((commits_scroll_idx as u16, 0));
-        let block_commits = Block::bordered();
+        let paragraph = Paragraph::new(lines);
+        let block_commits = Block::bordered().title(format!("Log ({commit_order})"));
         frame.render_widget(paragraph.block(block_commits), commit_area);
 
-        let paragraph = Paragraph::new(authors).scroll((commits_scroll_idx as u16, 0));
+        let paragraph = Paragraph::new(authors);
         let block_author = Block::bordered();
         frame.render_widget(paragraph.block(block_author), author_area);
 
-        let paragraph = Paragraph::new(times).scroll((commits_scroll_idx as u16, 0));
+        let paragraph = Paragraph::new(times);
         let block_times = Block::bordered();
         frame.render_widget(paragraph.block(block_times), times_area);
 
         Ok(())
     }
-    fn render_commit_area(&mut self, _diff_area: Rect) -> Result<RenderedDiff, std::io::Error> {
-        let _ = self
-            .get_or_refresh_selected_commit()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-        let Some(selected_commit) = self.selected_commit_cached.as_ref() else {
-            return Ok(RenderedDiff { texts: vec![] });
+    fn draw_selected_commit_area(
+        &mut self,
+        frame: &mut Frame,
+        diff_area: Rect,
+    ) -> Result<(), std::io::Error> {
+        let diff_scroll_idx = self.diff_scroll_idx;
+        let (files_focused, file_cursor) = (self.files_focused, self.file_cursor);
+        let file_filter = self.file_filter.clone();
+
+        let selection_idx = self.selection_idx;
+        if self.commits_shallow().get(selection_idx).is_none() {
+            return Ok(());
+        }
+        let Some(selected_commit) = self.selected_commit() else {
+            let paragraph = Paragraph::new("Loading…").block(Block::bordered());
+            frame.render_widget(paragraph, diff_area);
+            return Ok(());
+        };
+        let title = match selected_commit {
+            Detail::CommitDetail(selected_commit) => match selected_commit.merge_diff {
+                Some(merge_diff) => format!("Commit {} ({merge_diff})", selected_commit.id),
+                None => format!("Commit {}", selected_commit.id),
+            },
+            Detail::DiffTreeIndex(_) | Detail::DiffIndexCommit(_) => "Diff".to_string(),
+            Detail::Error(_) => "Error".to_string(),
+        };
+        let Some(rendered_diff) = self.rendered_diff() else {
+            return Ok(());
         };
-        Ok(match selected_commit {
+        if rendered_diff.files.is_empty() {
+            return Ok(());
+        }
+
+        let [commit_descr_area, files_area] = split_diff_area(diff_area);
+
+        // Mark the file that the top of the view is in, and the cursor if the list has the
+        // focus
+        let max_scroll = rendered_diff
+            .files
+            .iter()
+            .map(|file| file.lines.len())
+            .sum();
+        let current_file = rendered_diff
+            .file_at(diff_scroll_idx)
+            .filter(|_| diff_scroll_idx < max_scroll);
+        let cursor =
+            file_cursor_idx(rendered_diff, file_cursor, diff_scroll_idx).filter(|_| files_focused);
+        let listed = listed_files(rendered_diff, file_filter.as_deref());
+        let mut files_lines = Vec::with_capacity(listed.len());
+        let mut marked_row = 0;
+        for (row, file_idx) in listed.into_iter().enumerate() {
+            let file = &rendered_diff.files[file_idx];
+            let mut index_line = file.index_line.clone();
+            if file.folded {
+                index_line.push_span(Span::styled(" (folded)", Style::default().dark_gray()));
+            }
+            if Some(file_idx) == current_file {
+                index_line = index_line.patch_style(Style::default().bold().on_dark_gray());
+                marked_row = row;
+            }
+            if Some(file_idx) == cursor {
+                index_line = index_line.patch_style(Style::default().reversed());
+                marked_row = row;
+            }
+            files_lines.push(index_line);
+        }
+        // Keep the marked entry in view
+        let files_h = files_area.height.saturating_sub(2) as usize;
+        let files_scroll = (marked_row + 1).saturating_sub(files_h);
+
+        // Only clone the lines that can be visible, diffs can be large
+        let diff_scroll_idx = diff_scroll_idx.min(max_scroll);
+        let mut commit_descr_text = Text::from(
+            rendered_diff
+                .lines()
+                .skip(diff_scroll_idx)
+                .take(commit_descr_area.height as usize)
+                .cloned()
+                .collect::<Vec<_>>(),
+        );
+
+        if let Some(diff_search) = &self.diff_search {
+            diff_search.highlight(&mut commit_descr_text.lines, diff_scroll_idx);
+        }
+
+        let mut scrollbar_state = ScrollbarState::new(max_scroll);
+        scrollbar_state = scrollbar_state.position(diff_scroll_idx);
+
+        let paragraph = Paragraph::new(commit_descr_text).wrap(Wrap { trim: false });
+
+        let scrollbar_area = commit_descr_area.inner(ratatui::layout::Margin {
+            vertical: 0,
+            horizontal: 1,
+        });
+        frame.render_stateful_widget(
+            Scrollbar::new(ratatui::widgets::ScrollbarOrientation::VerticalRight),
+            scrollbar_area,
+            &mut scrollbar_state,
+        );
+
+        let block_selected = Block::bordered().title(title);
+        frame.render_widget(paragraph.block(block_selected), commit_descr_area);
+
+        self.last_files_scroll = files_scroll;
+        let paragraph = Paragraph::new(files_lines).scroll((files_scroll as u16, 0));
+        let mut block_files = Block::bordered();
+        if let Some(file_filter) = &file_filter {
+            block_files = block_files.title(format!("Files matching {file_filter}"));
+        }
+        if files_focused {
+            block_files = block_files.border_style(Style::default().yellow());
+        }
+        frame.render_widget(paragraph.block(block_files), files_area);
+
+        Ok(())
+    }
+    pub(crate) fn commits_authors_times_lines(&mut self, rows: Range<usize>) -> LogColumns<'_> {
+        // cache the commits to display so that we don't do IO at each render iteration
+        let selection_idx = self.selection_idx;
+        self.commits_shallow();
+        let commits_shallow = &self.commits_shallow_cached;
+        let search = self.search.as_ref();
+        let [mut lines, mut authors, mut times]: [Vec<_>; 3] = Default::default();
+
+        let selected_st = ratatui::style::Modifier::BOLD | ratatui::style::Modifier::UNDERLINED;
+        for (idx, cmt) in commits_shallow
+            .iter()
+            .enumerate()
+            .skip(rows.start)
+            .take(rows.len())
+        {
+            let matched = search.is_some_and(|search| search.matches(cmt));
+            let needle = search
+                .and_then(|search| search.text_needle())
+                .filter(|_| matched);
+            let highlight = |text: &str, style: Style| match needle {
+                Some(needle) => highlight_spans(text, needle, style),
+                None => vec![Span::styled(text.to_owned(), style)],
+            };
+            let commit_id_st = Style::default().yellow();
+            let mut commit_line = match cmt.id {
+                crate::model::ShallowId::CommitId(_id, prefix) => {
+                    // Mark matches in the message body or the diff, which aren't shown here
+                    let commit_id_st = if matched
+                        && needle.is_none_or(|needle| {
+                            !cmt.commit.to_lowercase().contains(needle)
+                                && !cmt.signature.to_string().to_lowercase().contains(needle)
+                        }) {
+                        commit_id_st.patch(match_style())
+                    } else {
+                        commit_id_st
+                    };
+                    let mut spans = graph_spans(&cmt.graph, '●');
+                    spans.push(Span::from(prefix.to_string()).style(commit_id_st));
+                    spans.push(Span::from(" "));
+                    spans.extend(highlight(&cmt.commit, Style::default()));
+                    Line::from(spans)
+                }
+                crate::model::ShallowId::Worktree | crate::model::ShallowId::Index => {
+                    let mut spans = graph_spans(&cmt.graph, '○');
+                    spans.push(Span::from(cmt.commit.clone()));
+                    Line::from(spans)
+                }
+            };
+            if !cmt.refs.is_empty() {
+                let refs_str = cmt.refs.join(", ");
+                commit_line
+                    .push_span(Span::from(format!(" ({refs_str})")).style(Style::default().red()));
+            }
+            let author_line = Line::from(highlight(&cmt.signature.to_string(), Style::default()));
+            if idx == selection_idx {
+                lines.push(commit_line.style(selected_st));
+                authors.push(author_line.style(selected_st));
+                times.push(Line::from(cmt.signature.time.clone()).style(selected_st));
+            } else {
+                lines.push(commit_line);
+                authors.push(author_line);
+                times.push(Line::from(cmt.signature.time.clone()));
+            }
+        }
+        (lines, authors, times)
+    }
+}
+
+impl DetailParams {
+    /// Renders the details of an entry of the log for the diff area
+    pub(crate) fn render_detail(&self, detail: &Detail, highlighter: &Highlighter) -> RenderedDiff {
+        let files = match detail {
             Detail::CommitDetail(selected_commit) => {
-                self.render_commit_area_commit(_diff_area, selected_commit)?
+                self.render_commit_area_commit(selected_commit, highlighter)
             }
             Detail::DiffIndexCommit(diff) | Detail::DiffTreeIndex(diff) => {
-                self.render_commit_area_diff(_diff_area, diff)?
+                self.render_commit_area_diff(diff, highlighter)
             }
             Detail::Error(e) => {
-                let diff = &Diff {
-                    files: vec![(
+                let diff = &Diff::new(
+                    vec![(
                         FileModificationKind::Modification,
                         "ERROR".into(),
                         format!("Error: {e:?}"),
                     )],
-                };
-                self.render_commit_area_diff(_diff_area, diff)?
+                    1,
+                );
+                self.render_commit_area_diff(diff, highlighter)
             }
-        })
-    }
-
-    fn render_commit_area_diff(
-        &self,
-        _diff_area: Rect,
-        diff: &Diff,
-    ) -> Result<RenderedDiff, std::io::Error> {
-        self.render_commit_area_diff_inner(_diff_area, diff, 0, true)
+        };
+        RenderedDiff::new(files)
     }
 
-    fn render_commit_area_diff_inner(
-        &self,
-        _diff_area: Rect,
-        diff: &Diff,
-        prior_text_len: usize,
-        can_set_bold: bool,
-    ) -> Result<RenderedDiff, std::io::Error> {
-        let mut bold_already_set = !can_set_bold;
-        let diff_scroll_idx = self.diff_scroll_idx;
-        let mut texts = Vec::new();
-
-        let mut len_ctr = prior_text_len;
+    fn render_commit_area_diff(&self, diff: &Diff, highlighter: &Highlighter) -> Vec<RenderedFile> {
+        let mut files = Vec::new();
+        let mut highlight_budget = MAX_HIGHLIGHTED_LINES;
+        let diff_parents = diff.parents;
+        // The bars of the diffstat are relative to the file with the most changes
+        let max_changes = diff
+            .files
+            .iter()
+            .map(|(_kind, _path, _diff, stat)| stat.insertions + stat.deletions)
+            .max()
+            .unwrap_or(0);
 
-        for (kind, path, diff) in diff.files.iter().filter(|(kind, _path, diff)| {
-            matches!(kind, crate::model::FileModificationKind::Rewrite(_))
+        // Empty diffs are only interesting if there is something else to show for the file
+        for (kind, path, diff, stat) in diff.files.iter().filter(|(kind, _path, diff, _stat)| {
+            !matches!(kind, crate::model::FileModificationKind::Modification)
                 || !diff.trim().is_empty()
         }) {
             let st = Style::default();
@@ -123,43 +405,67 @@ impl State {
                 crate::model::FileModificationKind::Modification => ('M', st.yellow()),
                 crate::model::FileModificationKind::Rewrite(_) => ('R', st.yellow()),
             };
-            let mut diff_for_file = Text::from(vec![Line::styled(
+            let mut header = vec![Line::styled(
                 dash_wrap(path),
                 Style::default().white().on_dark_gray(),
-            )]);
+            )];
             if let crate::model::FileModificationKind::Rewrite(source_loc) = kind {
                 let renamed_line = Line::styled(
                     format!("Renamed from: {source_loc}"),
                     Style::default().white().on_dark_gray(),
                 );
-                diff_for_file.extend(Text::from(vec![renamed_line]));
+                header.push(renamed_line);
+            }
+            let deleted_lines = stat.deletions;
+            let hunks = if matches!(kind, crate::model::FileModificationKind::Deletion)
+                && self.collapse_deletions
+                && deleted_lines > COLLAPSED_DELETION_LINES
+            {
+                header.push(Line::styled(
+                    format!("{deleted_lines} lines deleted"),
+                    Style::default().red(),
+                ));
+                Vec::new()
+            } else {
+                let (preamble, mut hunks) = parse_diff(
+                    diff,
+                    diff_parents,
+                    self.word_diff,
+                    self.diff_options.algorithm,
+                );
+                // Combined diffs have several marker columns, and word diffs keep their
+                // colors
+                if self.syntax_highlighting
+                    && diff_parents == 1
+                    && self.word_diff != WordDiff::Words
+                {
+                    highlighter.highlight_hunks(path, &mut hunks, &mut highlight_budget);
+                }
+                header.extend(preamble);
+                hunks
+            };
+            let has_sides = diff_parents == 1 && self.word_diff != WordDiff::Words;
+
+            let mut index_line = Line::from(format!("{kind_str} {path}")).style(style);
+            for span in diffstat_spans(*stat, max_changes) {
+                index_line.push_span(span);
             }
-            diff_for_file.extend(style_text_for_diff(diff));
-            diff_for_file.extend([Line::from("")]);
-
-            let style =
-                if len_ctr + diff_for_file.lines.len() > diff_scroll_idx && !bold_already_set {
-                    bold_already_set = true;
-                    style.bold().on_dark_gray()
-                } else {
-                    style
-                };
-            len_ctr += diff_for_file.lines.len();
-
-            let index_line = Line::from(format!("{kind_str} {path}")).style(style);
-            texts.push((index_line, diff_for_file));
+            files.push(RenderedFile::new(
+                index_line,
+                Some(path.clone()),
+                header,
+                hunks,
+                has_sides,
+            ));
         }
 
-        Ok(RenderedDiff { texts })
+        files
     }
     fn render_commit_area_commit(
         &self,
-        _diff_area: Rect,
         selected_commit: &CommitDetail,
-    ) -> Result<RenderedDiff, std::io::Error> {
-        let diff_scroll_idx = self.diff_scroll_idx;
-        let mut texts = Vec::new();
-
+        highlighter: &Highlighter,
+    ) -> Vec<RenderedFile> {
         fn line_with_kind<'a>(kind: &'a str, s: String) -> Line<'a> {
             Line::from(vec![Span::from(kind).bold(), Span::from(s)])
         }
@@ -169,154 +475,85 @@ impl State {
             .map(|(_oid, oid_prefix, ttl)| format!("{oid_prefix} {ttl}"))
             .collect::<Vec<String>>();
         let parents_str = parents_str.join(", ");
+        let diff = &selected_commit.diff_parent;
         let mut commit_descr_text = Text::from(vec![
             line_with_kind("Author: ", selected_commit.author.format_with_time()),
             line_with_kind("Committer: ", selected_commit.committer.format_with_time()),
             line_with_kind("Parents: ", parents_str),
+            line_with_kind("Changes: ", diffstat_summary(diff)),
             Line::from(""),
             Line::from(selected_commit.title.clone()),
             Line::from(""),
         ]);
         commit_descr_text.extend(Text::raw(selected_commit.msg_detail.clone()));
-        commit_descr_text.extend([Line::from("")]);
 
-        let mut bold_already_set = false;
-
-        let st = if commit_descr_text.lines.len() > diff_scroll_idx && !bold_already_set {
-            bold_already_set = true;
-            Style::default().bold().on_dark_gray()
-        } else {
-            Style::default()
-        };
+        let mut files = vec![RenderedFile::new(
+            Line::from("Description"),
+            None,
+            commit_descr_text.lines,
+            Vec::new(),
+            false,
+        )];
 
-        let descr_text_len = commit_descr_text.lines.len();
+        files.extend(self.render_commit_area_diff(diff, highlighter));
 
-        texts.push((Line::from("Description").style(st), commit_descr_text));
-
-        let diff = &selected_commit.diff_parent;
-
-        let texts_diff = self.render_commit_area_diff_inner(
-            _diff_area,
-            diff,
-            descr_text_len,
-            !bold_already_set,
-        )?;
-
-        texts.extend(texts_diff.texts);
-
-        Ok(RenderedDiff { texts })
+        files
     }
-    fn draw_selected_commit_area(
-        &mut self,
-        frame: &mut Frame,
-        diff_area: Rect,
-    ) -> Result<(), std::io::Error> {
-        let diff_scroll_idx = self.diff_scroll_idx;
-        let rendered_diff = self.render_commit_area(diff_area)?;
-        self.last_rendered_diff = Some(rendered_diff.clone());
-
-        let Some(selected_commit) = self
-            .get_or_refresh_selected_commit()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
-        else {
-            return Ok(());
-        };
-        if rendered_diff.texts.len() == 0 {
-            return Ok(());
-        }
-
-        let [commit_descr_area, files_area] =
-            Layout::horizontal([Constraint::Fill(3), Constraint::Fill(1)]).areas(diff_area);
-
-        let files_lines = rendered_diff
-            .texts
-            .iter()
-            .map(|l| l.0.clone())
-            .collect::<Vec<_>>();
-        let mut commit_descr_text =
-            rendered_diff
-                .texts
-                .into_iter()
-                .fold(Text::from(Vec::new()), |mut t_a, (_l_b, t_b)| {
-                    t_a.extend(t_b);
-                    t_a
-                });
-
-        let max_scroll = commit_descr_text.lines.len();
-
-        let mut scrollbar_state = ScrollbarState::new(max_scroll);
-        scrollbar_state = scrollbar_state.position(diff_scroll_idx);
-
-        let diff_scroll_idx = diff_scroll_idx.min(max_scroll);
-
-        let scrolled_lines = commit_descr_text.lines.split_off(diff_scroll_idx);
-
-        commit_descr_text.lines = scrolled_lines;
-
-        let paragraph = Paragraph::new(commit_descr_text)
-            .wrap(Wrap { trim: false });
-
-        let scrollbar_area = commit_descr_area.inner(ratatui::layout::Margin {
-            vertical: 0,
-            horizontal: 1,
-        });
-        frame.render_stateful_widget(
-            Scrollbar::new(ratatui::widgets::ScrollbarOrientation::VerticalRight),
-            scrollbar_area,
-            &mut scrollbar_state,
-        );
-
-        let title = match selected_commit {
-            Detail::CommitDetail(selected_commit) => format!("Commit {}", selected_commit.id),
-            Detail::DiffTreeIndex(_) | Detail::DiffIndexCommit(_) => format!("Diff"),
-            Detail::Error(_) => format!("Error"),
-        };
-        let block_selected = Block::bordered().title(title);
-        frame.render_widget(paragraph.block(block_selected), commit_descr_area);
+}
 
-        let paragraph = Paragraph::new(files_lines).wrap(Wrap { trim: true });
-        let block_selected = Block::bordered();
-        frame.render_widget(paragraph.block(block_selected), files_area);
+const LANE_COLORS: [Color; 6] = [
+    Color::Blue,
+    Color::Green,
+    Color::Magenta,
+    Color::Cyan,
+    Color::LightRed,
+    Color::LightYellow,
+];
+
+fn lane_style(lane: usize) -> Style {
+    Style::default().fg(LANE_COLORS[lane % LANE_COLORS.len()])
+}
 
-        Ok(())
+/// Draw the graph part of a log line with box drawing characters, two cells per lane
+fn graph_spans(row: &GraphRow, commit_char: char) -> Vec<Span<'static>> {
+    let width = row.width();
+    // Whether there is a horizontal line between lane idx and idx + 1
+    let mut horizontal = vec![false; width];
+    for &lane in row.forks.iter().chain(row.merges.iter()) {
+        let (start, end) = (lane.min(row.column), lane.max(row.column));
+        horizontal[start..end].iter_mut().for_each(|h| *h = true);
     }
-    pub(crate) fn commits_authors_times_lines(
-        &mut self,
-    ) -> Result<(Vec<Line<'_>>, Vec<Line<'_>>, Vec<Line<'_>>), anyhow::Error> {
-        // cache the commits to display so that we don't do IO at each render iteration
-        let selection_idx = self.selection_idx;
-        let commits_shallow = self.get_or_refresh_commits_shallow()?;
-        let [mut lines, mut authors, mut times]: [Vec<_>; 3] = Default::default();
+    let is_active = |lanes: &[bool], idx: usize| lanes.get(idx).copied().unwrap_or(false);
 
-        let selected_st = ratatui::style::Modifier::BOLD | ratatui::style::Modifier::UNDERLINED;
-        for (idx, cmt) in commits_shallow.iter().enumerate() {
-            let commit_id_st = Style::default().yellow();
-            let mut commit_line = match cmt.id {
-                crate::model::ShallowId::CommitId(_id, prefix) => Line::from(vec![
-                    Span::from(prefix.to_string()).style(commit_id_st),
-                    Span::from(format!(" {}", cmt.commit)),
-                ]),
-                crate::model::ShallowId::Worktree | crate::model::ShallowId::Index => {
-                    Line::from(cmt.commit.clone())
-                }
-            };
-            if !cmt.refs.is_empty() {
-                let refs_str = cmt.refs.join(", ");
-                commit_line
-                    .push_span(Span::from(format!(" ({refs_str})")).style(Style::default().red()));
-            }
-            if idx == selection_idx {
-                lines.push(commit_line.style(selected_st));
-                authors.push(Line::from(cmt.signature.to_string()).style(selected_st));
-                times.push(Line::from(cmt.signature.time.clone()).style(selected_st));
-            } else {
-                lines.push(commit_line);
-                authors.push(Line::from(cmt.signature.to_string()));
-                times.push(Line::from(cmt.signature.time.clone()));
+    let mut spans = Vec::with_capacity(width * 2);
+    for idx in 0..width {
+        let ch = if idx == row.column {
+            commit_char
+        } else {
+            let up = is_active(&row.above, idx);
+            let down = is_active(&row.below, idx);
+            let left = idx > 0 && horizontal[idx - 1];
+            let right = horizontal[idx];
+            match (up, down, left, right) {
+                (true, true, true, true) => '┼',
+                (true, true, true, false) => '┤',
+                (true, true, false, true) => '├',
+                (true, false, true, true) => '┴',
+                (false, true, true, true) => '┬',
+                (true, false, true, false) => '┘',
+                (true, false, false, true) => '└',
+                (false, true, true, false) => '┐',
+                (false, true, false, true) => '┌',
+                (false, false, true, _) | (false, false, _, true) => '─',
+                (true, _, false, false) | (_, true, false, false) => '│',
+                (false, false, false, false) => ' ',
             }
-        }
-        Ok((lines, authors, times))
+        };
+        spans.push(Span::from(ch.to_string()).style(lane_style(idx)));
+        let connector = if horizontal[idx] { "─" } else { " " };
+        spans.push(Span::from(connector).style(lane_style(row.column)));
     }
+    spans
 }
 
 /// Wrap the given string in dashes, i.e. `---- abc ----`
@@ -329,22 +566,3 @@ fn dash_wrap(s: &str) -> String {
     let pad_right = padding_len - pad_left;
     format!("{nothing:->pad_left$} {s} {nothing:->pad_right$}")
 }
-
-fn style_text_for_diff(diff: &str) -> Text<'static> {
-    let lines = diff
-        .lines()
-        .map(|line| {
-            let st = if line.starts_with('+') {
-                Style::default().green()
-            } else if line.starts_with('-') {
-                Style::default().red()
-            } else if line.starts_with("@@") {
-                Style::default().blue()
-            } else {
-                Style::default()
-            };
-            Line::from(line.to_owned()).style(st)
-        })
-        .collect::<Vec<_>>();
-    Text::from(lines)
-}
@@ -0,0 +1,95 @@
+use gix::bstr::ByteSlice;
+
+use crate::{State, rendered_diff::RenderedDiff};
+
+/// Whether the path matches the glob of the file list filter.
+///
+/// `*` also matches slashes, so `*.rs` matches in all directories. A filter without any
+/// wildcards matches the paths that contain it.
+fn filter_matches(filter: &str, path: &str) -> bool {
+    let pattern = if filter.contains(['*', '?', '[']) {
+        filter.to_owned()
+    } else {
+        format!("*{filter}*")
+    };
+    gix::glob::wildmatch(
+        pattern.as_bytes().as_bstr(),
+        path.as_bytes().as_bstr(),
+        gix::glob::wildmatch::Mode::empty(),
+    )
+}
+
+/// The indices of the entries of the file list that pass the filter.
+///
+/// Entries without a path, like the description, are always listed.
+pub(crate) fn listed_files(rendered_diff: &RenderedDiff, filter: Option<&str>) -> Vec<usize> {
+    rendered_diff
+        .files
+        .iter()
+        .enumerate()
+        .filter(|(_idx, file)| match (filter, &file.path) {
+            (Some(filter), Some(path)) => filter_matches(filter, path),
+            _ => true,
+        })
+        .map(|(idx, _file)| idx)
+        .collect()
+}
+
+/// The entry of the file list under the cursor, which is the file at the top of the diff
+/// area unless the cursor was moved
+pub(crate) fn file_cursor_idx(
+    rendered_diff: &RenderedDiff,
+    file_cursor: Option<usize>,
+    diff_scroll_idx: usize,
+) -> Option<usize> {
+    file_cursor
+        .filter(|idx| *idx < rendered_diff.files.len())
+        .or_else(|| rendered_diff.file_at(diff_scroll_idx))
+}
+
+impl State {
+    /// Moves the focus between the log and the file list
+    pub(crate) fn toggle_files_focus(&mut self) {
+        self.files_focused = !self.files_focused;
+        self.file_cursor = None;
+    }
+    /// Moves the cursor of the file list to the next or the previous listed file
+    pub(crate) fn move_file_cursor(&mut self, down: bool) {
+        let (file_cursor, sidx) = (self.file_cursor, self.diff_scroll_idx);
+        let filter = self.file_filter.clone();
+        let Some(rendered_diff) = self.rendered_diff() else {
+            return;
+        };
+        let listed = listed_files(rendered_diff, filter.as_deref());
+        let Some(cursor) = file_cursor_idx(rendered_diff, file_cursor, sidx) else {
+            return;
+        };
+        let target = if down {
+            listed.into_iter().find(|idx| *idx > cursor)
+        } else {
+            listed.into_iter().rev().find(|idx| *idx < cursor)
+        };
+        if let Some(target) = target {
+            self.file_cursor = Some(target);
+        }
+    }
+    /// Scrolls the diff area to the file under the cursor of the file list
+    pub(crate) fn jump_to_file_cursor(&mut self) {
+        let (file_cursor, sidx) = (self.file_cursor, self.diff_scroll_idx);
+        let Some(rendered_diff) = self.rendered_diff() else {
+            return;
+        };
+        let Some(cursor) = file_cursor_idx(rendered_diff, file_cursor, sidx) else {
+            return;
+        };
+        self.diff_scroll_idx = rendered_diff.file_starts()[cursor];
+        // The file is at the top now, so the cursor can follow the scrolling again
+        self.file_cursor = None;
+    }
+    /// Narrows the file list to the paths matching the glob, or lists all files again if it
+    /// is empty
+    pub(crate) fn set_file_filter(&mut self, filter: String) {
+        self.file_filter = (!filter.is_empty()).then_some(filter);
+        self.file_cursor = None;
+    }
+}
@@ -1,35 +1,248 @@
-use std::{collections::HashMap, ops::ControlFlow, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::ControlFlow,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::{Context, anyhow};
-use crossterm::event::KeyCode;
-use gix::{ObjectId, Repository, refs::Reference};
-use model::CommitShallow;
+use clap::Parser;
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture, KeyCode};
+use gix::{ObjectId, Repository, diff::blob::Algorithm, refs::Reference};
+use model::{CachedDetail, CommitShallow, DiffOptions, LogWalk, MergeDiff, ShallowId};
 use ratatui::{DefaultTerminal, crossterm::event, layout::Rect};
 
-use crate::{draw::RenderedDiff, model::Detail};
+use crate::{
+    cache::LruCache,
+    diff_search::DiffSearch,
+    keymap::{Action, Key, Keymap},
+    search::{Prompt, PromptKind, Search, SearchDirection},
+    walk::CommitOrder,
+    whitespace::IgnoreSpace,
+    word_diff::WordDiff,
+    worker::Workers,
+};
 
+mod cache;
+mod combined;
+mod diff_nav;
+mod diff_search;
 mod draw;
+mod file_list;
+mod graph;
+mod keymap;
 mod model;
+mod mouse;
+mod pickaxe;
+mod rendered_diff;
+mod search;
+mod syntax;
+mod walk;
+mod whitespace;
+mod word_diff;
+mod worker;
+
+/// Pure Rust gitk clone for the terminal
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Revisions to show the history of, like `main`, `a..b`, `a...b` or `^a` [default: HEAD]
+    revisions: Vec<String>,
+    /// Show the history of all refs, as well as HEAD
+    #[arg(long)]
+    all: bool,
+    /// Show the history of all branches
+    #[arg(long)]
+    branches: bool,
+    /// Show the history of all tags
+    #[arg(long)]
+    tags: bool,
+    /// Show the history of all remote-tracking branches
+    #[arg(long)]
+    remotes: bool,
+    /// Show no parents before all of their children, otherwise order by commit date
+    #[arg(long, group = "order")]
+    date_order: bool,
+    /// Show no parents before all of their children, otherwise order by author date
+    #[arg(long, group = "order")]
+    author_date_order: bool,
+    /// Show no parents before all of their children, and avoid intermixing lines of history
+    #[arg(long, group = "order")]
+    topo_order: bool,
+    /// Follow renames of the file, which must be the only path given
+    #[arg(long)]
+    follow: bool,
+    /// The diff algorithm, one of myers, minimal, patience or histogram [default: diff.algorithm]
+    #[arg(long, value_parser = model::parse_algorithm)]
+    diff_algorithm: Option<Algorithm>,
+    /// Number of unchanged lines to show around changes [default: 3]
+    #[arg(short = 'U', long)]
+    unified: Option<u32>,
+    /// Ignore whitespace when comparing lines
+    #[arg(short = 'w', long)]
+    ignore_all_space: bool,
+    /// Ignore changes in amount of whitespace, and whitespace at the end of lines
+    #[arg(short = 'b', long)]
+    ignore_space_change: bool,
+    /// Ignore carriage returns at the end of lines
+    #[arg(long)]
+    ignore_cr_at_eol: bool,
+    /// Ignore changes whose lines are all blank
+    #[arg(long)]
+    ignore_blank_lines: bool,
+    /// Show removed and added words inline instead of whole lines
+    #[arg(long)]
+    word_diff: bool,
+    /// Don't show line numbers in front of the lines of diffs, toggled with `#`
+    #[arg(long)]
+    no_line_numbers: bool,
+    /// Don't color the lines of diffs by the syntax of their files, toggled with `y`
+    #[arg(long)]
+    no_syntax_highlighting: bool,
+    /// Show large deleted files as a single line, toggled with `d`
+    #[arg(long)]
+    collapse_deletions: bool,
+    /// Only show commits that change the given paths
+    #[arg(last = true)]
+    paths: Vec<PathBuf>,
+}
+
+impl Args {
+    fn ignore_space(&self) -> IgnoreSpace {
+        if self.ignore_all_space {
+            IgnoreSpace::All
+        } else if self.ignore_space_change {
+            IgnoreSpace::Change
+        } else if self.ignore_cr_at_eol {
+            IgnoreSpace::CrAtEol
+        } else {
+            IgnoreSpace::None
+        }
+    }
+    fn commit_order(&self) -> CommitOrder {
+        if self.author_date_order {
+            CommitOrder::AuthorDate
+        } else if self.topo_order {
+            CommitOrder::Topo
+        } else {
+            CommitOrder::CommitterDate
+        }
+    }
+    fn revisions(&self) -> Vec<String> {
+        let mut revisions = self.revisions.clone();
+        if self.all || (revisions.is_empty() && self.ref_prefixes().is_empty()) {
+            revisions.push("HEAD".to_owned());
+        }
+        revisions
+    }
+    fn ref_prefixes(&self) -> Vec<&'static str> {
+        if self.all {
+            return vec!["refs/"];
+        }
+        let mut prefixes = Vec::new();
+        if self.branches {
+            prefixes.push("refs/heads/");
+        }
+        if self.tags {
+            prefixes.push("refs/tags/");
+        }
+        if self.remotes {
+            prefixes.push("refs/remotes/");
+        }
+        prefixes
+    }
+    fn paths(&self) -> Vec<PathBuf> {
+        // Paths are relative to the repository root, so drop any leading `./`
+        self.paths
+            .iter()
+            .map(|path| {
+                path.components()
+                    .filter(|c| *c != std::path::Component::CurDir)
+                    .collect::<PathBuf>()
+            })
+            .filter(|path| !path.as_os_str().is_empty())
+            .collect()
+    }
+}
 
 struct State {
     repo: Repository,
 
     wanted_commit_list_count: usize,
+    commit_order: CommitOrder,
+    revisions: Vec<String>,
+    ref_prefixes: Vec<&'static str>,
+    paths: Vec<PathBuf>,
+    follow: bool,
+
+    workers: Workers,
 
     // Model caches
-    commits_shallow_cached: Option<Vec<CommitShallow>>,
-    selected_commit_cached: Option<Detail>,
+    /// The log, which is only ever extended, unless the caches are invalidated
+    commits_shallow_cached: Vec<CommitShallow>,
+    /// The job extending the log, if one is running
+    commits_shallow_requested: Option<u64>,
+    /// The walk to extend the log with, unless a job has it
+    log_walk: Option<LogWalk>,
+    commits_walk_exhausted: bool,
+    /// Details of the recently shown entries of the log
+    details_cache: LruCache<ShallowId, CachedDetail>,
+    /// Entries of the log whose details the worker has yet to compute
+    details_requested: Vec<ShallowId>,
+    /// Changes with the settings the details are computed with, to tell outdated results apart
+    details_generation: u64,
+    /// The entry of the log that the details were last requested for
+    details_selected: Option<ShallowId>,
     worktree_index_changed_cached: Option<(bool, bool)>,
-    id_to_refs_map_cached: HashMap<ObjectId, Vec<Reference>>,
+    id_to_refs_map_cached: Arc<HashMap<ObjectId, Vec<Reference>>>,
 
     // UI states
+    keymap: Keymap,
+    /// Keys pressed so far of a sequence like `gg`
+    pending_keys: Vec<Key>,
+    /// Whether to show large deleted files as a single line
+    collapse_deletions: bool,
+    /// Whether to show untracked files in the worktree changes
+    show_untracked: bool,
+    /// What to diff merge commits against
+    merge_diff: MergeDiff,
+    /// How changes within lines are shown
+    word_diff: WordDiff,
+    /// Whether to show the old and new lines next to each other, if there is room
+    side_by_side: bool,
+    /// Whether to show the old and new line numbers in front of the lines of unified diffs
+    line_numbers: bool,
+    /// Paths of the files whose hunks are hidden, in the diffs of all commits
+    folded_paths: HashSet<String>,
+    /// Whether to color the lines of diffs by the syntax of their files
+    syntax_highlighting: bool,
+    /// Loaded when first needed, as that takes a moment
+    diff_options: DiffOptions,
     selection_idx: usize,
     diff_scroll_idx: usize,
     commits_scroll_idx: usize,
+    prompt: Option<Prompt>,
+    search: Option<Search>,
+    diff_search: Option<DiffSearch>,
+    /// Whether the arrow keys move the cursor of the file list instead of the log selection
+    files_focused: bool,
+    /// The entry of the file list that the cursor was moved to, otherwise it is on the file
+    /// at the top of the diff area
+    file_cursor: Option<usize>,
+    /// The glob that the paths in the file list have to match
+    file_filter: Option<String>,
+    /// A message to show in the line at the bottom
+    status: Option<String>,
+    /// The height of the log area that the divider was dragged to, otherwise it takes a third
+    log_height: Option<u16>,
+    /// Whether the divider between the log and the diff area is being dragged with the mouse
+    dragging_divider: bool,
 
-    last_rendered_diff: Option<RenderedDiff>,
     last_log_area: Rect,
     last_diff_area: Rect,
+    /// The first listed entry that the file list shows
+    last_files_scroll: usize,
 }
 
 struct App {
@@ -38,168 +251,413 @@ struct App {
 }
 
 impl State {
-    fn new() -> Result<State, anyhow::Error> {
+    fn new(args: &Args) -> Result<State, anyhow::Error> {
+        let repo = gix::open(".")?;
+        // Like git, use diff.algorithm unless told otherwise
+        let diff_options = DiffOptions {
+            algorithm: args
+                .diff_algorithm
+                .unwrap_or_else(|| repo.diff_algorithm().unwrap_or(Algorithm::Myers)),
+            context: args.unified.unwrap_or(DiffOptions::default().context),
+            ignore_space: args.ignore_space(),
+            ignore_blank_lines: args.ignore_blank_lines,
+        };
         let state = State {
-            repo: gix::open(".")?,
+            workers: Workers::spawn(&repo),
+            repo,
             wanted_commit_list_count: 10,
-            commits_shallow_cached: None,
-            selected_commit_cached: None,
+            commit_order: args.commit_order(),
+            revisions: args.revisions(),
+            ref_prefixes: args.ref_prefixes(),
+            paths: args.paths(),
+            follow: args.follow,
+            commits_shallow_cached: Vec::new(),
+            commits_shallow_requested: None,
+            log_walk: None,
+            commits_walk_exhausted: false,
+            details_cache: LruCache::new(DETAILS_CACHE_SIZE),
+            details_requested: Vec::new(),
+            details_generation: 0,
+            details_selected: None,
             worktree_index_changed_cached: None,
-            id_to_refs_map_cached: HashMap::new(),
+            id_to_refs_map_cached: Arc::new(HashMap::new()),
 
+            keymap: Keymap::load()?,
+            pending_keys: Vec::new(),
+            collapse_deletions: args.collapse_deletions,
+            show_untracked: true,
+            merge_diff: MergeDiff::Parent(0),
+            word_diff: if args.word_diff {
+                WordDiff::Words
+            } else {
+                WordDiff::default()
+            },
+            side_by_side: false,
+            line_numbers: !args.no_line_numbers,
+            folded_paths: HashSet::new(),
+            syntax_highlighting: !args.no_syntax_highlighting,
+            diff_options,
             selection_idx: 0,
             diff_scroll_idx: 0,
             commits_scroll_idx: 0,
-            last_rendered_diff: None,
+            prompt: None,
+            search: None,
+            diff_search: None,
+            files_focused: false,
+            file_cursor: None,
+            file_filter: None,
+            status: None,
+            log_height: None,
+            dragging_divider: false,
             last_log_area: Rect::new(0, 0, 0, 0),
             last_diff_area: Rect::new(0, 0, 0, 0),
+            last_files_scroll: 0,
         };
+        if state.follow && state.paths.len() != 1 {
+            anyhow::bail!("--follow requires exactly one path");
+        }
+        // Report invalid revisions right away instead of inside the TUI
+        state.resolve_revisions()?;
         Ok(state)
     }
 }
 
+/// Number of entries of the log to keep the details of
+const DETAILS_CACHE_SIZE: usize = 32;
+
 const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const LOADING_POLL_INTERVAL: Duration = Duration::from_millis(5);
 
 impl App {
-    fn new(terminal: DefaultTerminal) -> Result<App, anyhow::Error> {
-        let app = App {
-            state: State::new()?,
-            terminal,
-        };
+    fn new(terminal: DefaultTerminal, state: State) -> Result<App, anyhow::Error> {
+        let app = App { state, terminal };
         Ok(app)
     }
     fn run(&mut self) -> Result<(), anyhow::Error> {
         self.state.compute_id_to_refs_map();
         loop {
+            self.state.receive_worker_results();
             self.terminal.try_draw(|frame| self.state.draw(frame))?;
-            if event::poll(POLL_INTERVAL).context("failed to poll for events")? {
+            // Don't wait for events while there is work to do, and check back often
+            // for results of the workers
+            let poll_interval = if self.state.search_needs_commits() {
+                Duration::ZERO
+            } else if self.state.loading() {
+                LOADING_POLL_INTERVAL
+            } else {
+                POLL_INTERVAL
+            };
+            if event::poll(poll_interval).context("failed to poll for events")? {
                 let event = event::read().context("failed to read event")?;
                 match self.handle_event(event) {
                     ControlFlow::Break(()) => break,
                     ControlFlow::Continue(()) => (),
                 }
+            } else {
+                self.state.continue_search();
             }
         }
         Ok(())
     }
     fn handle_event(&mut self, event: event::Event) -> ControlFlow<(), ()> {
-        let log_h = self.state.last_log_area.height.saturating_sub(2);
-        let diff_h = self.state.last_diff_area.height.saturating_sub(2);
         match event {
+            event::Event::Key(key) if self.state.prompt.is_some() => {
+                self.handle_prompt_key(key.code);
+            }
+            event::Event::Key(key)
+                if key.code == KeyCode::Esc
+                    && (self.state.search_pending() || self.state.search_running()) =>
+            {
+                self.state.cancel_search();
+            }
+            event::Event::Key(key)
+                if key.code == KeyCode::Esc && self.state.diff_search.is_some() =>
+            {
+                // Leave the search in the diff area
+                self.state.diff_search = None;
+                self.state.status = None;
+            }
+            event::Event::Key(key)
+                if key.code == KeyCode::Esc && !self.state.pending_keys.is_empty() =>
+            {
+                // Abort the sequence of keys
+                self.state.pending_keys.clear();
+            }
+            event::Event::Key(key) if key.code == KeyCode::Esc && self.state.files_focused => {
+                // Give the focus back to the log
+                self.state.toggle_files_focus();
+            }
             event::Event::Key(key) => {
-                if key.code == KeyCode::Char('q') || key.code == KeyCode::Esc {
-                    // Quit the application using q
-                    return ControlFlow::Break(());
-                } else if key.code == KeyCode::Down || key.code == KeyCode::Char('k') {
-                    // Scroll down log area
-                    self.handle_log_select_down(1);
-                } else if key.code == KeyCode::Up || key.code == KeyCode::Char('i') {
-                    // Scroll up log area
-                    self.handle_log_select_up(1);
-                } else if key.code == KeyCode::PageDown || key.code == KeyCode::Char('K') {
-                    // Scroll down log area alot
-                    self.handle_log_select_down(log_h as usize / 2);
-                } else if key.code == KeyCode::PageUp || key.code == KeyCode::Char('I') {
-                    // Scroll up log area alot
-                    self.handle_log_select_up(log_h as usize / 2);
-                } else if key.code == KeyCode::Char('l') {
-                    // Scroll down commit area
-                    self.handle_diff_scroll_down(1);
-                } else if key.code == KeyCode::Char('o') {
-                    // Scroll up commit area
-                    self.handle_diff_scroll_up(1);
-                } else if key.code == KeyCode::Char('L') {
-                    // Scroll down commit area alot
-                    self.handle_diff_scroll_down(diff_h as usize / 2);
-                } else if key.code == KeyCode::Char('O') {
-                    // Scroll up commit area
-                    self.handle_diff_scroll_up(diff_h as usize / 2);
-                } else if key.code == KeyCode::Char('w') {
-                    // Scroll up commit area to prev file
-                    if let Some(rendered_diff) = &self.state.last_rendered_diff {
-                        let mut ctr = 0;
-                        let mut last_ctr = 0;
-                        let sidx = self.state.diff_scroll_idx;
-                        for (_line, text) in &rendered_diff.texts {
-                            last_ctr = ctr;
-                            let len = text.lines.len();
-                            if sidx > ctr && sidx <= ctr + len {
-                                self.state.diff_scroll_idx = ctr;
-                                break;
-                            }
-                            ctr += len;
-                        }
-                        if sidx > ctr {
-                            self.state.diff_scroll_idx = last_ctr;
-                        }
-                    }
-                } else if key.code == KeyCode::Char('s') {
-                    // Scroll down commit area to next file
-                    if let Some(rendered_diff) = &self.state.last_rendered_diff {
-                        let mut ctr = 0;
-                        let sidx = self.state.diff_scroll_idx;
-                        for (_line, text) in rendered_diff.texts.iter().rev().skip(1).rev() {
-                            let len = text.lines.len();
-                            if sidx >= ctr && sidx < ctr + len {
-                                self.state.diff_scroll_idx = ctr + len;
-                                break;
-                            }
-                            ctr += len;
-                        }
-                    }
+                self.state.status = None;
+                if let Some(action) = self.state.key_action(key) {
+                    return self.handle_action(action);
                 }
             }
             event::Event::FocusGained => (),
             event::Event::FocusLost => (),
-            event::Event::Mouse(_) => (),
+            event::Event::Mouse(mouse) => self.state.handle_mouse(mouse),
             event::Event::Paste(_) => (),
-            event::Event::Resize(_, _) => {
+            event::Event::Resize(_, _) => (),
+        }
+        ControlFlow::Continue(())
+    }
+    fn handle_action(&mut self, action: Action) -> ControlFlow<(), ()> {
+        let log_h = self.state.last_log_area.height.saturating_sub(2);
+        let diff_h = self.state.last_diff_area.height.saturating_sub(2);
+        match action {
+            // Quit the application
+            Action::Quit => return ControlFlow::Break(()),
+            // Move the focus between the log and the file list
+            Action::FocusFiles => self.state.toggle_files_focus(),
+            // Move the cursor of the file list down
+            Action::SelectDown if self.state.files_focused => self.state.move_file_cursor(true),
+            // Move the cursor of the file list up
+            Action::SelectUp if self.state.files_focused => self.state.move_file_cursor(false),
+            // Scroll the commit area to the file under the cursor
+            Action::OpenFile => self.state.jump_to_file_cursor(),
+            // Scroll down log area
+            Action::SelectDown => self.handle_log_select_down(1),
+            // Scroll up log area
+            Action::SelectUp => self.handle_log_select_up(1),
+            // Scroll down log area alot
+            Action::SelectPageDown => self.handle_log_select_down(log_h as usize / 2),
+            // Scroll up log area alot
+            Action::SelectPageUp => self.handle_log_select_up(log_h as usize / 2),
+            // Select the first entry of the log
+            Action::SelectFirst => self.handle_log_select_up(self.state.selection_idx),
+            // Select the last entry of the log loaded so far
+            Action::SelectLast => {
+                let last_idx = self.state.commits_shallow_cached.len().saturating_sub(1);
+                self.handle_log_select_down(last_idx.saturating_sub(self.state.selection_idx));
+            }
+            // Scroll down commit area
+            Action::ScrollDown => self.handle_diff_scroll_down(1),
+            // Scroll up commit area
+            Action::ScrollUp => self.handle_diff_scroll_up(1),
+            // Scroll down commit area alot
+            Action::ScrollPageDown => self.handle_diff_scroll_down(diff_h as usize / 2),
+            // Scroll up commit area alot
+            Action::ScrollPageUp => self.handle_diff_scroll_up(diff_h as usize / 2),
+            // Scroll commit area to the top
+            Action::ScrollTop => self.state.diff_scroll_idx = 0,
+            // Scroll commit area to the bottom
+            Action::ScrollBottom => self.state.scroll_diff_to_end(),
+            Action::CycleCommitOrder => {
+                // Switch to the next commit order
+                self.state.commit_order = self.state.commit_order.next();
+                self.state.selection_idx = 0;
+                self.state.commits_scroll_idx = 0;
+                self.state.diff_scroll_idx = 0;
+                self.state.file_cursor = None;
+                self.state.invalidate_caches();
+            }
+            Action::ToggleCollapseDeletions => {
+                // Toggle collapsing of large deleted files
+                self.state.collapse_deletions = !self.state.collapse_deletions;
+                self.state.invalidate_details();
+            }
+            Action::ToggleUntracked => {
+                // Toggle showing untracked files, like `git status -uno`
+                self.state.show_untracked = !self.state.show_untracked;
+                // Whether there are worktree changes depends on it, which adds or removes
+                // a row at the top of the log
+                self.state.worktree_index_changed_cached = None;
+                self.state.selection_idx = 0;
+                self.state.commits_scroll_idx = 0;
+                self.state.diff_scroll_idx = 0;
+                self.state.file_cursor = None;
                 self.state.invalidate_caches();
             }
+            // Switch to the next diff algorithm
+            Action::CycleDiffAlgorithm => self.state.cycle_diff_algorithm(),
+            // Show more lines of context around changes
+            Action::MoreContext => self.state.change_diff_context(true),
+            // Show fewer lines of context around changes
+            Action::LessContext => self.state.change_diff_context(false),
+            // Switch to the next way of ignoring whitespace
+            Action::CycleIgnoreSpace => self.state.cycle_ignore_space(),
+            // Toggle ignoring changes of blank lines
+            Action::ToggleIgnoreBlankLines => self.state.toggle_ignore_blank_lines(),
+            // Switch to the next way of showing changes within lines
+            Action::CycleWordDiff => self.state.cycle_word_diff(),
+            // Toggle showing diffs side by side
+            Action::ToggleSideBySide => self.state.toggle_side_by_side(),
+            // Scroll down commit area to next hunk
+            Action::NextHunk => self.state.jump_to_hunk(true),
+            // Scroll up commit area to previous hunk
+            Action::PrevHunk => self.state.jump_to_hunk(false),
+            // Fold or unfold the file at the top of the commit area
+            Action::ToggleFold => self.state.toggle_fold(),
+            // Fold or unfold all files of the commit area
+            Action::ToggleFoldAll => self.state.toggle_fold_all(),
+            // Toggle the line numbers in front of the lines of diffs
+            Action::ToggleLineNumbers => self.state.toggle_line_numbers(),
+            // Toggle syntax highlighting
+            Action::ToggleSyntaxHighlighting => self.state.toggle_syntax_highlighting(),
+            // Switch what the diff of the selected merge commit is against
+            Action::CycleMergeDiff => self.state.cycle_merge_diff(),
+            Action::FilterFiles => {
+                // Open the prompt to filter the file list by a glob
+                self.state.prompt = Some(Prompt {
+                    kind: PromptKind::FileFilter,
+                    input: self.state.file_filter.clone().unwrap_or_default(),
+                });
+            }
+            Action::SearchMessages => {
+                // Open the prompt to search commit messages
+                self.state.prompt = Some(Prompt {
+                    kind: PromptKind::MessageSearch,
+                    input: String::new(),
+                });
+            }
+            Action::SearchDiff => {
+                // Open the prompt to search the text of the diff area
+                self.state.prompt = Some(Prompt {
+                    kind: PromptKind::DiffSearch,
+                    input: String::new(),
+                });
+            }
+            Action::SearchOccurrences => {
+                // Open the prompt to search for commits changing the number of
+                // occurrences of a string
+                self.state.prompt = Some(Prompt {
+                    kind: PromptKind::PickaxeOccurrences,
+                    input: String::new(),
+                });
+            }
+            Action::SearchLinesMatching => {
+                // Open the prompt to search for commits changing lines matching a regex
+                self.state.prompt = Some(Prompt {
+                    kind: PromptKind::PickaxeLinesMatching,
+                    input: String::new(),
+                });
+            }
+            Action::NextMatch => {
+                // Select the next search match
+                if self.state.diff_search.is_some() {
+                    self.state.find_diff_match(SearchDirection::Forward);
+                } else {
+                    self.state.find_match(SearchDirection::Forward);
+                }
+            }
+            Action::PrevMatch => {
+                // Select the previous search match
+                if self.state.diff_search.is_some() {
+                    self.state.find_diff_match(SearchDirection::Backward);
+                } else {
+                    self.state.find_match(SearchDirection::Backward);
+                }
+            }
+            Action::PrevFile => {
+                // Scroll up commit area to prev file
+                let sidx = self.state.diff_scroll_idx;
+                if let Some(rendered_diff) = self.state.rendered_diff() {
+                    let mut ctr = 0;
+                    let mut last_ctr = 0;
+                    let mut new_sidx = sidx;
+                    for file in &rendered_diff.files {
+                        last_ctr = ctr;
+                        let len = file.lines.len();
+                        if sidx > ctr && sidx <= ctr + len {
+                            new_sidx = ctr;
+                            break;
+                        }
+                        ctr += len;
+                    }
+                    if sidx > ctr {
+                        new_sidx = last_ctr;
+                    }
+                    self.state.diff_scroll_idx = new_sidx;
+                }
+            }
+            Action::NextFile => {
+                // Scroll down commit area to next file
+                let sidx = self.state.diff_scroll_idx;
+                if let Some(rendered_diff) = self.state.rendered_diff() {
+                    let mut ctr = 0;
+                    let mut new_sidx = sidx;
+                    for file in rendered_diff.files.iter().rev().skip(1).rev() {
+                        let len = file.lines.len();
+                        if sidx >= ctr && sidx < ctr + len {
+                            new_sidx = ctr + len;
+                            break;
+                        }
+                        ctr += len;
+                    }
+                    self.state.diff_scroll_idx = new_sidx;
+                }
+            }
         }
         ControlFlow::Continue(())
     }
-    fn handle_log_select_down(&mut self, amount: usize) {
-        let log_h = self.state.last_log_area.height.saturating_sub(2);
-
-        let amount = if let Some(commits) = &self.state.commits_shallow_cached {
-            // Scroll down only as far as the commits permit, i.e. don't support scrolling past
-            // the last commit
-            let new_selection_idx = (commits.len() - 1).min(self.state.selection_idx + amount);
-            new_selection_idx - self.state.selection_idx
-        } else {
+    fn handle_prompt_key(&mut self, code: KeyCode) {
+        let Some(prompt) = &mut self.state.prompt else {
             return;
         };
-
-        self.state.selection_idx += amount;
-
-        if !self.state.last_log_area.is_empty() {
-            // Scroll down if we are at the bottom
-            let selection_idx = self.state.selection_idx;
-            if selection_idx >= self.state.commits_scroll_idx + log_h as usize {
-                self.state.commits_scroll_idx += amount;
+        match code {
+            KeyCode::Esc => self.state.prompt = None,
+            KeyCode::Enter => {
+                let Some(prompt) = self.state.prompt.take() else {
+                    return;
+                };
+                // `n` and `N` go through the matches of the search started last
+                if !matches!(prompt.kind, PromptKind::DiffSearch | PromptKind::FileFilter) {
+                    self.state.diff_search = None;
+                }
+                match prompt.kind {
+                    PromptKind::MessageSearch => self.state.start_message_search(prompt.input),
+                    kind @ (PromptKind::PickaxeOccurrences | PromptKind::PickaxeLinesMatching) => {
+                        self.state.start_pickaxe_search(kind, prompt.input)
+                    }
+                    PromptKind::DiffSearch => self.state.start_diff_search(prompt.input),
+                    PromptKind::FileFilter => self.state.set_file_filter(prompt.input),
+                }
+            }
+            KeyCode::Backspace => {
+                prompt.input.pop();
             }
+            KeyCode::Char(c) => prompt.input.push(c),
+            _ => (),
         }
+    }
+    fn handle_log_select_down(&mut self, amount: usize) {
+        let commits = &self.state.commits_shallow_cached;
+        if commits.is_empty() {
+            return;
+        }
+        // Scroll down only as far as the commits permit, i.e. don't support scrolling past
+        // the last commit
+        let new_selection_idx = (commits.len() - 1).min(self.state.selection_idx + amount);
+        let amount = new_selection_idx - self.state.selection_idx;
+
+        self.state.selection_idx += amount;
+        self.scroll_selection_into_view();
 
         self.state.diff_scroll_idx = 0;
-        self.state.invalidate_caches();
+        self.state.file_cursor = None;
     }
     fn handle_log_select_up(&mut self, amount: usize) {
-        self.state.selection_idx = self.state.selection_idx.saturating_sub(amount as usize);
-
-        if !self.state.last_log_area.is_empty() {
-            // Scroll dup if we are at the top
-            let selection_idx = self.state.selection_idx;
-            if selection_idx < self.state.commits_scroll_idx {
-                self.state.commits_scroll_idx = self
-                    .state
-                    .commits_scroll_idx
-                    .saturating_sub(amount as usize);
-            }
-        }
+        self.state.selection_idx = self.state.selection_idx.saturating_sub(amount);
+        self.scroll_selection_into_view();
 
         self.state.diff_scroll_idx = 0;
-        self.state.invalidate_caches();
+        self.state.file_cursor = None;
+    }
+    /// Scrolls the log just enough to show the selection, which the mouse wheel might have
+    /// scrolled out of view
+    fn scroll_selection_into_view(&mut self) {
+        if self.state.last_log_area.is_empty() {
+            return;
+        }
+        let log_h = self.state.last_log_area.height.saturating_sub(2) as usize;
+        let selection_idx = self.state.selection_idx;
+        if selection_idx < self.state.commits_scroll_idx {
+            // Scroll up if we are above the top
+            self.state.commits_scroll_idx = selection_idx;
+        } else if selection_idx >= self.state.commits_scroll_idx + log_h {
+            // Scroll down if we are below the bottom
+            self.state.commits_scroll_idx = (selection_idx + 1).saturating_sub(log_h);
+        }
     }
     fn handle_diff_scroll_up(&mut self, amount: usize) {
         self.state.diff_scroll_idx = self.state.diff_scroll_idx.saturating_sub(amount);
@@ -211,9 +669,15 @@ impl App {
 
 fn main() -> Result<(), anyhow::Error> {
     color_eyre::install().map_err(|err| anyhow!("{}", color_eyre::Report::msg(err)))?;
+    let args = Args::parse();
+    let state = State::new(&args)?;
     let terminal = ratatui::init();
-    let mut app = App::new(terminal)?;
-    app.run()?;
+    crossterm::execute!(std::io::stdout(), EnableMouseCapture)
+        .context("failed to enable mouse capture")?;
+    let mut app = App::new(terminal, state)?;
+    let result = app.run();
+    crossterm::execute!(std::io::stdout(), DisableMouseCapture)
+        .context("failed to disable mouse capture")?;
     ratatui::restore();
-    Ok(())
+    result
 }
@@ -0,0 +1,110 @@
+use gix::ObjectId;
+
+/// The part of the commit graph that is drawn in the row of a single commit
+#[derive(Clone)]
+pub(crate) struct GraphRow {
+    /// The lane that the commit itself is in
+    pub(crate) column: usize,
+    /// Which lanes are active in the space above the commit
+    pub(crate) above: Vec<bool>,
+    /// Which lanes are active in the space below the commit
+    pub(crate) below: Vec<bool>,
+    /// Lanes coming from above that end in the commit, as it is where they forked off
+    pub(crate) forks: Vec<usize>,
+    /// Lanes going downwards to the commit's parents, apart from the first one
+    pub(crate) merges: Vec<usize>,
+}
+
+impl GraphRow {
+    /// Number of lanes that the row needs space for
+    pub(crate) fn width(&self) -> usize {
+        self.above.len().max(self.below.len()).max(self.column + 1)
+    }
+}
+
+/// Assigns lanes to commits, which must be pushed in an order where children come before parents
+#[derive(Default)]
+pub(crate) struct GraphBuilder {
+    /// For each lane, the commit that the lane is leading to
+    lanes: Vec<Option<ObjectId>>,
+}
+
+impl GraphBuilder {
+    /// Computes the row for the next commit.
+    ///
+    /// Entries without an id, like the worktree and index entries, are put onto the lane
+    /// of their first parent if it already has one.
+    pub(crate) fn push(&mut self, id: Option<ObjectId>, parents: &[ObjectId]) -> GraphRow {
+        let above = self.lanes.iter().map(Option::is_some).collect::<Vec<_>>();
+
+        let lane_of = |lanes: &[Option<ObjectId>], id: ObjectId| {
+            lanes.iter().position(|lane| *lane == Some(id))
+        };
+        let expecting = if let Some(id) = id {
+            (0..self.lanes.len())
+                .filter(|idx| self.lanes[*idx] == Some(id))
+                .collect::<Vec<_>>()
+        } else {
+            parents
+                .first()
+                .and_then(|parent_id| lane_of(&self.lanes, *parent_id))
+                .into_iter()
+                .collect()
+        };
+        let column = match expecting.first() {
+            Some(column) => *column,
+            None => self.free_lane(&above),
+        };
+        let forks = expecting.get(1..).unwrap_or_default().to_vec();
+        for fork in &forks {
+            self.lanes[*fork] = None;
+        }
+
+        self.lanes[column] = parents.first().copied();
+        let mut merges = Vec::new();
+        for parent_id in parents.iter().skip(1) {
+            let lane = match lane_of(&self.lanes, *parent_id) {
+                Some(lane) => lane,
+                None => {
+                    let lane = self.free_lane(&above);
+                    self.lanes[lane] = Some(*parent_id);
+                    lane
+                }
+            };
+            if lane != column && !merges.contains(&lane) {
+                merges.push(lane);
+            }
+        }
+
+        while self.lanes.last() == Some(&None) {
+            self.lanes.pop();
+        }
+        let below = self.lanes.iter().map(Option::is_some).collect();
+        GraphRow {
+            column,
+            above,
+            below,
+            forks,
+            merges,
+        }
+    }
+    /// Lets a commit that is not shown pass on its lanes to its parent, so that the lanes
+    /// lead to the next shown commit instead
+    pub(crate) fn skip(&mut self, id: ObjectId, parent: Option<ObjectId>) {
+        for lane in self.lanes.iter_mut().filter(|lane| **lane == Some(id)) {
+            *lane = parent;
+        }
+        while self.lanes.last() == Some(&None) {
+            self.lanes.pop();
+        }
+    }
+    /// Finds a lane that is free both above and below the current row, adding one if needed
+    fn free_lane(&mut self, above: &[bool]) -> usize {
+        let free = (0..self.lanes.len())
+            .find(|idx| self.lanes[*idx].is_none() && !above.get(*idx).copied().unwrap_or(false));
+        free.unwrap_or_else(|| {
+            self.lanes.push(None);
+            self.lanes.len() - 1
+        })
+    }
+}
@@ -0,0 +1,570 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{Context, anyhow, bail};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+use crate::State;
+
+/// Something that keys can be bound to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Action {
+    Quit,
+    SelectDown,
+    SelectUp,
+    SelectPageDown,
+    SelectPageUp,
+    SelectFirst,
+    SelectLast,
+    ScrollDown,
+    ScrollUp,
+    ScrollPageDown,
+    ScrollPageUp,
+    ScrollTop,
+    ScrollBottom,
+    NextFile,
+    PrevFile,
+    NextHunk,
+    PrevHunk,
+    ToggleFold,
+    ToggleFoldAll,
+    FocusFiles,
+    OpenFile,
+    FilterFiles,
+    CycleCommitOrder,
+    ToggleCollapseDeletions,
+    ToggleUntracked,
+    CycleDiffAlgorithm,
+    MoreContext,
+    LessContext,
+    CycleIgnoreSpace,
+    ToggleIgnoreBlankLines,
+    CycleWordDiff,
+    ToggleSideBySide,
+    ToggleLineNumbers,
+    ToggleSyntaxHighlighting,
+    CycleMergeDiff,
+    SearchMessages,
+    SearchDiff,
+    SearchOccurrences,
+    SearchLinesMatching,
+    NextMatch,
+    PrevMatch,
+}
+
+/// The names of the actions in the config file
+const ACTION_NAMES: &[(&str, Action)] = &[
+    ("quit", Action::Quit),
+    ("select-down", Action::SelectDown),
+    ("select-up", Action::SelectUp),
+    ("select-page-down", Action::SelectPageDown),
+    ("select-page-up", Action::SelectPageUp),
+    ("select-first", Action::SelectFirst),
+    ("select-last", Action::SelectLast),
+    ("scroll-down", Action::ScrollDown),
+    ("scroll-up", Action::ScrollUp),
+    ("scroll-page-down", Action::ScrollPageDown),
+    ("scroll-page-up", Action::ScrollPageUp),
+    ("scroll-top", Action::ScrollTop),
+    ("scroll-bottom", Action::ScrollBottom),
+    ("next-file", Action::NextFile),
+    ("prev-file", Action::PrevFile),
+    ("next-hunk", Action::NextHunk),
+    ("prev-hunk", Action::PrevHunk),
+    ("toggle-fold", Action::ToggleFold),
+    ("toggle-fold-all", Action::ToggleFoldAll),
+    ("focus-files", Action::FocusFiles),
+    ("open-file", Action::OpenFile),
+    ("filter-files", Action::FilterFiles),
+    ("cycle-commit-order", Action::CycleCommitOrder),
+    ("toggle-collapse-deletions", Action::ToggleCollapseDeletions),
+    ("toggle-untracked", Action::ToggleUntracked),
+    ("cycle-diff-algorithm", Action::CycleDiffAlgorithm),
+    ("more-context", Action::MoreContext),
+    ("less-context", Action::LessContext),
+    ("cycle-ignore-space", Action::CycleIgnoreSpace),
+    ("toggle-ignore-blank-lines", Action::ToggleIgnoreBlankLines),
+    ("cycle-word-diff", Action::CycleWordDiff),
+    ("toggle-side-by-side", Action::ToggleSideBySide),
+    ("toggle-line-numbers", Action::ToggleLineNumbers),
+    (
+        "toggle-syntax-highlighting",
+        Action::ToggleSyntaxHighlighting,
+    ),
+    ("cycle-merge-diff", Action::CycleMergeDiff),
+    ("search-messages", Action::SearchMessages),
+    ("search-diff", Action::SearchDiff),
+    ("search-occurrences", Action::SearchOccurrences),
+    ("search-lines-matching", Action::SearchLinesMatching),
+    ("next-match", Action::NextMatch),
+    ("prev-match", Action::PrevMatch),
+];
+
+impl Action {
+    fn from_name(name: &str) -> Option<Action> {
+        ACTION_NAMES
+            .iter()
+            .find(|(action_name, _action)| *action_name == name)
+            .map(|(_name, action)| *action)
+    }
+    fn name(self) -> &'static str {
+        ACTION_NAMES
+            .iter()
+            .find(|(_name, action)| *action == self)
+            .map_or("", |(name, _action)| name)
+    }
+}
+
+/// A key with the modifiers that bindings can distinguish
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Key {
+        match code {
+            // Like vim, Ctrl-D is the same as Ctrl-d
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::CONTROL) => Key {
+                code: KeyCode::Char(c.to_ascii_lowercase()),
+                modifiers: modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT),
+            },
+            // Shift is already in the case of characters and in back tab, and terminals
+            // differ in reporting it
+            KeyCode::Char(_) | KeyCode::BackTab => Key {
+                code,
+                modifiers: modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT),
+            },
+            _ => Key { code, modifiers },
+        }
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(key: KeyEvent) -> Key {
+        Key::new(key.code, key.modifiers)
+    }
+}
+
+/// Parses a key in angle brackets, like `Down` or `C-d`
+fn parse_named_key(name: &str) -> Result<Key, anyhow::Error> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = name;
+    while let Some((modifier, after)) = rest.split_once('-')
+        && !after.is_empty()
+    {
+        modifiers |= match modifier.to_ascii_lowercase().as_str() {
+            "c" => KeyModifiers::CONTROL,
+            "a" | "m" => KeyModifiers::ALT,
+            "s" => KeyModifiers::SHIFT,
+            _ => bail!("unknown modifier `{modifier}` in `<{name}>`"),
+        };
+        rest = after;
+    }
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match rest.to_ascii_lowercase().as_str() {
+            "down" => KeyCode::Down,
+            "up" => KeyCode::Up,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "pagedown" => KeyCode::PageDown,
+            "pageup" => KeyCode::PageUp,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "tab" if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+            "tab" => KeyCode::Tab,
+            "enter" | "cr" | "return" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "space" => KeyCode::Char(' '),
+            "bs" | "backspace" => KeyCode::Backspace,
+            "del" | "delete" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "lt" => KeyCode::Char('<'),
+            other => match other.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                Some(n) => KeyCode::F(n),
+                None => bail!("unknown key `<{name}>`"),
+            },
+        },
+    };
+    Ok(Key::new(code, modifiers))
+}
+
+/// Parses a sequence of keys in the notation of vim, like `gg`, `<C-d>` or `]<Tab>`
+fn parse_keys(text: &str) -> Result<Vec<Key>, anyhow::Error> {
+    let mut keys = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let named = (c == '<')
+            .then(|| rest[1..].split_once('>'))
+            .flatten()
+            .filter(|(name, _after)| !name.is_empty());
+        if let Some((name, after)) = named {
+            keys.push(parse_named_key(name)?);
+            rest = after;
+        } else {
+            keys.push(Key::new(KeyCode::Char(c), KeyModifiers::NONE));
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    if keys.is_empty() {
+        bail!("empty key sequence");
+    }
+    Ok(keys)
+}
+
+/// A set of bindings that the config file can start from
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Preset {
+    /// The keys giv always had, with `i`, `k`, `o` and `l` to move around
+    #[default]
+    Current,
+    /// Vim-like keys, like `j`, `k`, `gg`, `G`, `Ctrl-d` and `Ctrl-u`
+    Vim,
+    /// Keys like those of less, that mostly scroll the diff
+    Less,
+}
+
+impl Preset {
+    fn bindings(self) -> Vec<(Action, &'static [&'static str])> {
+        let mut bindings: Vec<(Action, &'static [&'static str])> = vec![
+            (Action::CycleCommitOrder, &["t"]),
+            (Action::CycleDiffAlgorithm, &["a"]),
+            (Action::MoreContext, &["+"]),
+            (Action::LessContext, &["-"]),
+            (Action::CycleIgnoreSpace, &["W"]),
+            (Action::ToggleIgnoreBlankLines, &["B"]),
+            (Action::ToggleSideBySide, &["v"]),
+            (Action::ToggleLineNumbers, &["#"]),
+            (Action::CycleMergeDiff, &["m"]),
+            (Action::SearchMessages, &["/"]),
+            (Action::SearchDiff, &["<C-f>"]),
+            (Action::SearchOccurrences, &["f"]),
+            (Action::SearchLinesMatching, &["F"]),
+            (Action::NextMatch, &["n"]),
+            (Action::PrevMatch, &["N"]),
+            (Action::FocusFiles, &["<Tab>"]),
+            (Action::OpenFile, &["<Enter>"]),
+        ];
+        bindings.extend_from_slice(match self {
+            Preset::Current => &[
+                (Action::Quit, &["q", "<Esc>"]),
+                (Action::SelectDown, &["k", "<Down>"]),
+                (Action::SelectUp, &["i", "<Up>"]),
+                (Action::SelectPageDown, &["K", "<PageDown>"]),
+                (Action::SelectPageUp, &["I", "<PageUp>"]),
+                (Action::SelectFirst, &["<Home>"]),
+                (Action::SelectLast, &["<End>"]),
+                (Action::ScrollDown, &["l"]),
+                (Action::ScrollUp, &["o"]),
+                (Action::ScrollPageDown, &["L"]),
+                (Action::ScrollPageUp, &["O"]),
+                (Action::NextFile, &["s"]),
+                (Action::PrevFile, &["w"]),
+                (Action::NextHunk, &["]"]),
+                (Action::PrevHunk, &["["]),
+                (Action::ToggleFold, &["z"]),
+                (Action::ToggleFoldAll, &["Z"]),
+                (Action::FilterFiles, &["g"]),
+                (Action::ToggleCollapseDeletions, &["d"]),
+                (Action::ToggleUntracked, &["u"]),
+                (Action::CycleWordDiff, &["e"]),
+                (Action::ToggleSyntaxHighlighting, &["y"]),
+            ],
+            Preset::Vim => &[
+                (Action::Quit, &["q"]),
+                (Action::SelectDown, &["j", "<Down>"]),
+                (Action::SelectUp, &["k", "<Up>"]),
+                (Action::SelectPageDown, &["<PageDown>"]),
+                (Action::SelectPageUp, &["<PageUp>"]),
+                (Action::SelectFirst, &["gg", "<Home>"]),
+                (Action::SelectLast, &["G", "<End>"]),
+                (Action::ScrollDown, &["<C-e>"]),
+                (Action::ScrollUp, &["<C-y>"]),
+                (Action::ScrollPageDown, &["<C-d>"]),
+                (Action::ScrollPageUp, &["<C-u>"]),
+                (Action::NextFile, &["}"]),
+                (Action::PrevFile, &["{"]),
+                (Action::NextHunk, &["]c"]),
+                (Action::PrevHunk, &["[c"]),
+                (Action::ToggleFold, &["za"]),
+                (Action::ToggleFoldAll, &["zA"]),
+                (Action::FilterFiles, &["gf"]),
+                (Action::ToggleCollapseDeletions, &["d"]),
+                (Action::ToggleUntracked, &["u"]),
+                (Action::CycleWordDiff, &["e"]),
+                (Action::ToggleSyntaxHighlighting, &["y"]),
+            ],
+            Preset::Less => &[
+                (Action::Quit, &["q", "Q"]),
+                (Action::SelectDown, &["J", "<Down>"]),
+                (Action::SelectUp, &["K", "<Up>"]),
+                (Action::SelectPageDown, &["<PageDown>"]),
+                (Action::SelectPageUp, &["<PageUp>"]),
+                (Action::SelectFirst, &["<Home>"]),
+                (Action::SelectLast, &["<End>"]),
+                (Action::ScrollDown, &["j", "e", "<C-e>", "<C-n>"]),
+                (Action::ScrollUp, &["k", "y", "<C-y>", "<C-p>"]),
+                (Action::ScrollPageDown, &["d", "<C-d>", "<Space>"]),
+                (Action::ScrollPageUp, &["u", "<C-u>", "b"]),
+                (Action::ScrollTop, &["g", "<lt>"]),
+                (Action::ScrollBottom, &["G", ">"]),
+                (Action::NextFile, &["}"]),
+                (Action::PrevFile, &["{"]),
+                (Action::NextHunk, &["]"]),
+                (Action::PrevHunk, &["["]),
+                (Action::ToggleFold, &["z"]),
+                (Action::ToggleFoldAll, &["Z"]),
+                (Action::FilterFiles, &["&"]),
+                (Action::ToggleCollapseDeletions, &["D"]),
+                (Action::ToggleUntracked, &["U"]),
+                (Action::CycleWordDiff, &["E"]),
+                (Action::ToggleSyntaxHighlighting, &["Y"]),
+            ],
+        });
+        bindings
+    }
+}
+
+/// The keys for an action in the config file, either a single sequence or a list of them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConfigKeys {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    preset: Preset,
+    /// Keys for actions, replacing the ones of the preset
+    keys: BTreeMap<String, ConfigKeys>,
+}
+
+/// `~/.config/giv/config.toml`, or in `$XDG_CONFIG_HOME` if that is set
+fn config_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".config")))?;
+    Some(config_dir.join("giv").join("config.toml"))
+}
+
+/// Whether pressing the keys of one binding would trigger, or wait for, the other one
+fn conflicts(a: &[Key], b: &[Key]) -> bool {
+    a.starts_with(b) || b.starts_with(a)
+}
+
+/// What the keys pressed so far lead to
+enum Lookup {
+    Action(Action),
+    /// More keys are needed to tell the action
+    Prefix,
+    None,
+}
+
+pub(crate) struct Keymap {
+    bindings: Vec<(Vec<Key>, Action)>,
+}
+
+impl Keymap {
+    /// Loads the keymap from the config file, or uses the default preset if there is none
+    pub(crate) fn load() -> Result<Keymap, anyhow::Error> {
+        let Some(path) = config_path().filter(|path| path.exists()) else {
+            return Keymap::new(Config::default());
+        };
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let config =
+            toml::from_str(&text).with_context(|| format!("failed to parse {}", path.display()))?;
+        Keymap::new(config).with_context(|| format!("invalid key bindings in {}", path.display()))
+    }
+    fn new(config: Config) -> Result<Keymap, anyhow::Error> {
+        let mut problems = Vec::new();
+        let mut configured_actions = Vec::new();
+        let mut configured = Vec::new();
+        for (name, keys) in &config.keys {
+            let Some(action) = Action::from_name(name) else {
+                problems.push(format!("unknown action `{name}`"));
+                continue;
+            };
+            configured_actions.push(action);
+            let keys = match keys {
+                ConfigKeys::One(keys) => std::slice::from_ref(keys),
+                ConfigKeys::Many(keys) => keys.as_slice(),
+            };
+            for text in keys {
+                match parse_keys(text) {
+                    Ok(keys) => configured.push((keys, text.clone(), action)),
+                    Err(err) => problems.push(format!("{err} for `{name}`")),
+                }
+            }
+        }
+        // The bindings, with the text of their keys for messages
+        let mut bindings = Vec::new();
+        for (action, keys) in config.preset.bindings() {
+            // Actions in the config file lose all of the keys of the preset
+            if configured_actions.contains(&action) {
+                continue;
+            }
+            for text in keys {
+                let keys = parse_keys(text).map_err(|err| anyhow!("{err} in preset"))?;
+                // Keys in the config file take precedence over the ones of the preset
+                let overridden = configured
+                    .iter()
+                    .any(|(configured, _text, _action)| conflicts(configured, &keys));
+                if !overridden {
+                    bindings.push((keys, text.to_string(), action));
+                }
+            }
+        }
+        bindings.extend(configured);
+        for (idx, (keys, text, action)) in bindings.iter().enumerate() {
+            for (other_keys, other_text, other_action) in &bindings[..idx] {
+                if conflicts(keys, other_keys) {
+                    problems.push(format!(
+                        "`{text}` for `{}` conflicts with `{other_text}` for `{}`",
+                        action.name(),
+                        other_action.name(),
+                    ));
+                }
+            }
+        }
+        if !problems.is_empty() {
+            bail!("{}", problems.join("\n"));
+        }
+        let bindings = bindings
+            .into_iter()
+            .map(|(keys, _text, action)| (keys, action))
+            .collect();
+        Ok(Keymap { bindings })
+    }
+    fn lookup(&self, keys: &[Key]) -> Lookup {
+        let mut lookup = Lookup::None;
+        for (binding, action) in &self.bindings {
+            if binding == keys {
+                return Lookup::Action(*action);
+            } else if binding.starts_with(keys) {
+                lookup = Lookup::Prefix;
+            }
+        }
+        lookup
+    }
+}
+
+impl State {
+    /// The action that the key completes, if any.
+    ///
+    /// Keys that start a sequence of keys are kept until the sequence is complete.
+    pub(crate) fn key_action(&mut self, key: KeyEvent) -> Option<Action> {
+        let key = Key::from(key);
+        let mut keys = std::mem::take(&mut self.pending_keys);
+        keys.push(key);
+        let mut lookup = self.keymap.lookup(&keys);
+        if matches!(lookup, Lookup::None) && keys.len() > 1 {
+            // The key might start a new sequence instead of continuing the old one
+            keys = vec![key];
+            lookup = self.keymap.lookup(&keys);
+        }
+        match lookup {
+            Lookup::Action(action) => Some(action),
+            Lookup::Prefix => {
+                self.pending_keys = keys;
+                None
+            }
+            Lookup::None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pressed(code: KeyCode, modifiers: KeyModifiers) -> Key {
+        Key::from(KeyEvent::new(code, modifiers))
+    }
+
+    fn keymap(config: &str) -> Result<Keymap, anyhow::Error> {
+        Keymap::new(toml::from_str(config)?)
+    }
+
+    #[test]
+    fn notation_matches_pressed_keys() {
+        let ctrl_d = pressed(KeyCode::Char('d'), KeyModifiers::CONTROL);
+        assert_eq!(parse_keys("<C-d>").unwrap(), [ctrl_d]);
+        assert_eq!(parse_keys("<c-D>").unwrap(), [ctrl_d]);
+        // Terminals may report the shift along with an uppercase letter
+        let ctrl_shift_d = pressed(
+            KeyCode::Char('D'),
+            KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+        );
+        assert_eq!(ctrl_shift_d, ctrl_d);
+
+        let back_tab = pressed(KeyCode::BackTab, KeyModifiers::SHIFT);
+        assert_eq!(parse_keys("<S-Tab>").unwrap(), [back_tab]);
+        assert_eq!(back_tab, pressed(KeyCode::BackTab, KeyModifiers::NONE));
+
+        let lt = pressed(KeyCode::Char('<'), KeyModifiers::SHIFT);
+        assert_eq!(parse_keys("<lt>").unwrap(), [lt]);
+        assert_eq!(parse_keys("<").unwrap(), [lt]);
+
+        let g = pressed(KeyCode::Char('g'), KeyModifiers::NONE);
+        assert_eq!(parse_keys("gg").unwrap(), [g, g]);
+        assert_eq!(
+            parse_keys("]<Tab>").unwrap(),
+            [
+                pressed(KeyCode::Char(']'), KeyModifiers::NONE),
+                pressed(KeyCode::Tab, KeyModifiers::NONE),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_notation_is_rejected() {
+        assert!(parse_keys("").is_err());
+        assert!(parse_keys("<X-d>").is_err());
+        assert!(parse_keys("<Nope>").is_err());
+    }
+
+    #[test]
+    fn sequences_are_looked_up_key_by_key() {
+        let keymap = keymap("preset = \"vim\"").unwrap();
+        let g = pressed(KeyCode::Char('g'), KeyModifiers::NONE);
+        assert!(matches!(keymap.lookup(&[g]), Lookup::Prefix));
+        assert!(matches!(
+            keymap.lookup(&[g, g]),
+            Lookup::Action(Action::SelectFirst)
+        ));
+        assert!(matches!(keymap.lookup(&[g, g, g]), Lookup::None));
+    }
+
+    #[test]
+    fn configured_keys_replace_the_preset() {
+        let keymap = keymap("[keys]\nscroll-down = \"q\"").unwrap();
+        let q = pressed(KeyCode::Char('q'), KeyModifiers::NONE);
+        let l = pressed(KeyCode::Char('l'), KeyModifiers::NONE);
+        assert!(matches!(
+            keymap.lookup(&[q]),
+            Lookup::Action(Action::ScrollDown)
+        ));
+        assert!(matches!(keymap.lookup(&[l]), Lookup::None));
+    }
+
+    #[test]
+    fn conflicting_bindings_are_rejected() {
+        let Err(err) = keymap("[keys]\nquit = \"x\"\nscroll-down = \"xy\"") else {
+            panic!("conflicting bindings were accepted");
+        };
+        assert_eq!(
+            err.to_string(),
+            "`xy` for `scroll-down` conflicts with `x` for `quit`"
+        );
+        assert!(keymap("[keys]\nquit = [\"x\", \"x\"]").is_err());
+        assert!(keymap("[keys]\nno-such-action = \"x\"").is_err());
+    }
+}
@@ -1,27 +1,44 @@
-use std::collections::BTreeSet;
-use std::collections::{HashMap, HashSet};
+use std::{collections::HashMap, sync::Arc};
 
 use anyhow::Context;
 use gix::{
-    ObjectId,
+    ObjectId, Repository,
     actor::SignatureRef,
+    bstr::BStr,
     diff::blob::{
-        UnifiedDiff,
+        Algorithm, Sink, UnifiedDiff,
         unified_diff::{ConsumeBinaryHunk, ContextSize},
     },
     hash::Prefix,
+    refs::Reference,
+    status::{
+        index_worktree::RewriteSource,
+        plumbing::index_as_worktree::{Change, EntryStatus},
+    },
 };
 
-use crate::State;
+use crate::{
+    State,
+    combined::combined_diff,
+    draw::diff_text_width,
+    graph::{GraphBuilder, GraphRow},
+    rendered_diff::{DiffLayout, RenderedDiff},
+    walk::{CommitOrder, CommitWalk, PathLimit},
+    whitespace::{IgnoreSpace, line_changes},
+    word_diff::WordDiff,
+    worker::JobResult,
+};
 
 pub(crate) struct CommitShallow {
     pub(crate) id: ShallowId,
     pub(crate) commit: String,
+    pub(crate) body: String,
     pub(crate) refs: Vec<String>,
     pub(crate) signature: Signature,
+    pub(crate) graph: GraphRow,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub(crate) enum ShallowId {
     CommitId(ObjectId, Prefix),
     Worktree,
@@ -41,10 +58,41 @@ pub(crate) struct CommitDetail {
     pub(crate) msg_detail: String,
     pub(crate) parents: Vec<(ObjectId, Prefix, String)>,
     pub(crate) diff_parent: Diff,
+    /// What the diff of a merge commit is against, `None` for other commits
+    pub(crate) merge_diff: Option<MergeDiff>,
     pub(crate) id: ObjectId,
 }
 
-#[allow(dead_code)]
+/// What to diff merge commits against
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum MergeDiff {
+    /// The parent with the given index, the first one is the mainline
+    Parent(usize),
+    /// All parents at once, showing only the hunks that differ from all of them
+    Combined,
+}
+
+impl MergeDiff {
+    /// The mode to switch to after this one, for a merge with the given number of parents
+    pub(crate) fn next(self, parents: usize) -> MergeDiff {
+        match self {
+            MergeDiff::Parent(idx) if idx + 1 < parents => MergeDiff::Parent(idx + 1),
+            MergeDiff::Parent(_) => MergeDiff::Combined,
+            MergeDiff::Combined => MergeDiff::Parent(0),
+        }
+    }
+}
+
+impl std::fmt::Display for MergeDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeDiff::Parent(idx) => write!(f, "diff to parent {}", idx + 1),
+            MergeDiff::Combined => write!(f, "combined diff"),
+        }
+    }
+}
+
+#[allow(dead_code, clippy::large_enum_variant)]
 pub(crate) enum Detail {
     DiffTreeIndex(Diff),
     DiffIndexCommit(Diff),
@@ -52,6 +100,12 @@ pub(crate) enum Detail {
     Error(anyhow::Error),
 }
 
+/// The details of an entry of the log, and what they look like in the diff area
+pub(crate) struct CachedDetail {
+    pub(crate) detail: Detail,
+    pub(crate) rendered: RenderedDiff,
+}
+
 pub(crate) enum FileModificationKind {
     Addition,
     Deletion,
@@ -60,7 +114,54 @@ pub(crate) enum FileModificationKind {
 }
 
 pub(crate) struct Diff {
-    pub(crate) files: Vec<(FileModificationKind, String, String)>,
+    pub(crate) files: Vec<(FileModificationKind, String, String, DiffStat)>,
+    /// Number of columns with `+` and `-` markers in front of the lines, one per parent
+    pub(crate) parents: usize,
+}
+
+impl Diff {
+    pub(crate) fn new(files: Vec<(FileModificationKind, String, String)>, parents: usize) -> Diff {
+        let files = files
+            .into_iter()
+            .map(|(kind, path, diff)| {
+                let stat = DiffStat::of(&diff, parents);
+                (kind, path, diff, stat)
+            })
+            .collect();
+        Diff { files, parents }
+    }
+    /// The numbers of added and removed lines of all files together
+    pub(crate) fn stat(&self) -> DiffStat {
+        let mut total = DiffStat::default();
+        for (_kind, _path, _diff, stat) in &self.files {
+            total.insertions += stat.insertions;
+            total.deletions += stat.deletions;
+        }
+        total
+    }
+}
+
+/// The numbers of added and removed lines, like `git diff --stat` shows them
+#[derive(Clone, Copy, Default)]
+pub(crate) struct DiffStat {
+    pub(crate) insertions: usize,
+    pub(crate) deletions: usize,
+}
+
+impl DiffStat {
+    /// Counts the changed lines of the diff text, which has a column of markers per parent
+    fn of(diff: &str, parents: usize) -> DiffStat {
+        let mut stat = DiffStat::default();
+        for line in diff.lines().filter(|line| !line.starts_with("@@")) {
+            let markers = line.get(..parents).unwrap_or(line);
+            if markers.contains('+') {
+                stat.insertions += 1;
+            } else if markers.contains('-') {
+                stat.deletions += 1;
+            }
+        }
+        stat
+    }
 }
 
 impl std::fmt::Display for Signature {
@@ -76,452 +177,267 @@ impl Signature {
 }
 
 impl State {
-    fn make_signature(&self, sig: SignatureRef<'_>) -> Result<Signature, anyhow::Error> {
-        Ok(Signature {
-            author_name: sig.name.to_string().trim().to_owned(),
-            author_email: sig.email.to_string().trim().to_owned(),
-            time: sig.time()?.format(gix::date::time::format::ISO8601)?,
-        })
+    fn log_params(&self) -> LogParams {
+        LogParams {
+            commit_order: self.commit_order,
+            revisions: self.revisions.clone(),
+            ref_prefixes: self.ref_prefixes.clone(),
+            path_limit: self.path_limit(),
+            budget: self.wanted_commit_list_count,
+            id_to_refs_map: self.id_to_refs_map_cached.clone(),
+            worktree_index_changed: self.worktree_index_changed_cached,
+            show_untracked: self.show_untracked,
+        }
     }
-    pub(crate) fn get_or_refresh_commits_shallow(
-        &mut self,
-    ) -> Result<&[CommitShallow], anyhow::Error> {
-        if self.commits_shallow_cached.is_none() {
-            let mut res = Vec::new();
-
-            let (worktree_changes, index_changes) = self.has_worktree_index_changes()?;
-
-            if worktree_changes {
-                res.push(CommitShallow {
-                    id: ShallowId::Worktree,
-                    commit: format!("Worktree changes, not in index"),
-                    refs: Vec::new(),
-                    signature: Signature {
-                        author_name: String::new(),
-                        author_email: String::new(),
-                        time: String::new(),
-                    },
-                });
-            }
-            if index_changes {
-                res.push(CommitShallow {
-                    id: ShallowId::Index,
-                    commit: format!("Index changes, not in a commit"),
-                    refs: Vec::new(),
-                    signature: Signature {
-                        author_name: String::new(),
-                        author_email: String::new(),
-                        time: String::new(),
-                    },
-                });
-            }
-
-            let head_commit = self.repo.head_commit()?;
-
-            let budget = self.wanted_commit_list_count;
-
-            let mut seen = HashSet::new();
-            let mut to_handle = BTreeSet::new();
-
-            to_handle.insert(((), head_commit.id));
-
-            while let Some((_commit_date, commit_id)) = to_handle.pop_first() {
-                if res.len() > budget {
-                    break;
-                }
-                let commit = self.repo.find_commit(commit_id)?;
-                for parent_id in commit.parent_ids() {
-                    if !seen.insert(parent_id.detach()) {
-                        continue;
-                    }
-                    to_handle.insert(((), parent_id.detach()));
-                }
-                let msg = commit.message()?;
-                let title = msg.title.to_string();
-                let refs_id = self.id_to_refs_map_cached.get(&commit.id);
-                let refs = if let Some(refs_id) = refs_id {
-                    refs_id
-                        .iter()
-                        .map(|ref_| format!("{}", ref_.name.as_bstr()).trim().to_owned())
-                        .collect()
-                } else {
-                    Vec::new()
-                };
-                res.push(CommitShallow {
-                    id: ShallowId::CommitId(commit.id, commit.short_id()?),
-                    commit: format!("{}", title.trim()),
-                    refs,
-                    signature: self.make_signature(commit.author()?)?,
-                });
-            }
-            Ok(self.commits_shallow_cached.insert(res))
-        } else {
-            Ok(self.commits_shallow_cached.as_ref().unwrap())
+    fn detail_params(&self) -> DetailParams {
+        DetailParams {
+            show_untracked: self.show_untracked,
+            merge_diff: self.merge_diff,
+            diff_options: self.diff_options,
+            collapse_deletions: self.collapse_deletions,
+            word_diff: self.word_diff,
+            syntax_highlighting: self.syntax_highlighting,
         }
     }
-    pub(crate) fn has_worktree_index_changes(&mut self) -> Result<(bool, bool), anyhow::Error> {
-        if let Some(cached) = self.worktree_index_changed_cached {
-            return Ok(cached);
+    /// The commits of the log.
+    ///
+    /// If fewer commits than wanted have been loaded, the worker is asked to continue the
+    /// walk, and until it is done, the commits loaded so far are returned.
+    pub(crate) fn commits_shallow(&mut self) -> &[CommitShallow] {
+        if self.commits_shallow_wanted() && self.commits_shallow_requested.is_none() {
+            let generation = self
+                .workers
+                .request_log(self.log_params(), self.log_walk.take());
+            self.commits_shallow_requested = Some(generation);
         }
-
-        let iter = self
-            .repo
-            .status(gix::progress::Discard)?
-            .index_worktree_rewrites(None)
-            .index_worktree_submodules(gix::status::Submodule::AsConfigured { check_dirty: true })
-            .untracked_files(gix::status::UntrackedFiles::Collapsed)
-            .index_worktree_options_mut(|opts| {
-                opts.dirwalk_options = None;
-            })
-            .into_iter(Vec::new())?;
-
-        let mut worktree_changes = false;
-        let mut index_changes = false;
-
-        for it in iter {
-            match it? {
-                gix::status::Item::IndexWorktree(_) => worktree_changes = true,
-                gix::status::Item::TreeIndex(_) => index_changes = true,
-            }
+        &self.commits_shallow_cached
+    }
+    /// Whether more commits are wanted than have been loaded so far
+    fn commits_shallow_wanted(&self) -> bool {
+        !self.commits_walk_exhausted
+            && self.commits_shallow_cached.len() <= self.wanted_commit_list_count
+    }
+    /// Whether more commits are wanted, and are yet to arrive
+    pub(crate) fn commits_shallow_loading(&self) -> bool {
+        self.commits_shallow_requested.is_some() || self.commits_shallow_wanted()
+    }
+    /// The details of the selected commit, or `None` while they are being computed
+    pub(crate) fn selected_commit(&mut self) -> Option<&Detail> {
+        self.request_details();
+        let id = self.commits_shallow_cached.get(self.selection_idx)?.id;
+        self.details_cache.get(&id).map(|cached| &cached.detail)
+    }
+    /// The rendered details of the selected commit, or `None` while they are being computed
+    pub(crate) fn rendered_diff(&mut self) -> Option<&RenderedDiff> {
+        self.request_details();
+        let id = self.commits_shallow_cached.get(self.selection_idx)?.id;
+        let layout = self.diff_layout();
+        let rendered = &mut self.details_cache.get(&id)?.rendered;
+        if rendered.layout != Some(layout) {
+            rendered.lay_out(layout, &self.folded_paths);
         }
-        let res = (worktree_changes, index_changes);
-        self.worktree_index_changed_cached = Some(res);
-
-        Ok(res)
-    }
-    pub(crate) fn get_or_refresh_selected_commit(
-        &mut self,
-    ) -> Result<Option<&Detail>, anyhow::Error> {
-        if self.selected_commit_cached.is_none() {
-            let selected_opt_res = self.get_selected_commit();
-            let selected_opt = match selected_opt_res {
-                Ok(v) => v,
-                Err(e) => Some(Detail::Error(e)),
-            };
-            if let Some(selected) = selected_opt {
-                Ok(Some(self.selected_commit_cached.insert(selected)))
-            } else {
-                Ok(None)
+        Some(rendered)
+    }
+    /// The layout of the diff area, side by side only if it was asked for and there is room
+    fn diff_layout(&self) -> DiffLayout {
+        let side_by_side = DiffLayout::side_by_side(diff_text_width(self.last_diff_area));
+        match side_by_side {
+            Some(layout) if self.side_by_side => layout,
+            _ => DiffLayout::Unified {
+                line_numbers: self.line_numbers,
+            },
+        }
+    }
+    /// Toggles showing the old and new lines of diffs next to each other
+    pub(crate) fn toggle_side_by_side(&mut self) {
+        self.side_by_side = !self.side_by_side;
+        self.diff_scroll_idx = 0;
+        self.status = Some(match self.diff_layout() {
+            DiffLayout::SideBySide(_) => "Showing diffs side by side".to_owned(),
+            DiffLayout::Unified { .. } if self.side_by_side => {
+                "Side by side diffs need a wider terminal, showing unified diffs".to_owned()
             }
+            DiffLayout::Unified { .. } => "Showing unified diffs".to_owned(),
+        });
+    }
+    /// Toggles showing the old and new line numbers in front of the lines of unified diffs
+    pub(crate) fn toggle_line_numbers(&mut self) {
+        self.line_numbers = !self.line_numbers;
+        self.status = Some(if self.line_numbers {
+            "Showing line numbers".to_owned()
         } else {
-            Ok(self.selected_commit_cached.as_ref())
-        }
+            "Hiding line numbers".to_owned()
+        });
     }
-    fn get_selected_commit(&mut self) -> Result<Option<Detail>, anyhow::Error> {
-        let selection_idx = self.selection_idx;
-        let index_id = {
-            let selected_hash = self.get_or_refresh_commits_shallow()?;
-            let Some(selected_commit) = selected_hash.get(selection_idx) else {
-                return Ok(None);
-            };
-            selected_commit.id
+    /// Switches the diff of the selected merge commit to the next parent, or to the
+    /// combined diff after the last parent
+    pub(crate) fn cycle_merge_diff(&mut self) {
+        let Some(Detail::CommitDetail(detail)) = self.selected_commit() else {
+            return;
         };
-        let id = match index_id {
-            ShallowId::CommitId(id, _prefix) => id,
-            ShallowId::Worktree => {
-                return Ok(Some(Detail::DiffTreeIndex(
-                    self.compute_diff_worktree_to_index()?,
-                )));
-            }
-            ShallowId::Index => {
-                return Ok(Some(Detail::DiffIndexCommit(
-                    self.compute_diff_index_to_commit()?,
-                )));
-            }
+        let Some(merge_diff) = detail.merge_diff else {
+            self.status = Some("Not a merge commit".to_owned());
+            return;
         };
-
-        let commit = self.repo.find_commit(id)?;
-        let msg = commit.message()?;
-        let title = msg.title.to_string().trim().to_owned();
-        let msg_detail = if let Some(body) = msg.body() {
-            body.without_trailer().to_string()
+        self.merge_diff = merge_diff.next(detail.parents.len());
+        self.diff_scroll_idx = 0;
+        self.invalidate_details();
+    }
+    /// Switches to the next way of showing changes within lines
+    pub(crate) fn cycle_word_diff(&mut self) {
+        self.word_diff = self.word_diff.next();
+        self.status = Some(format!("Showing {}", self.word_diff));
+        self.invalidate_details();
+    }
+    /// Toggles coloring the lines of diffs by the syntax of their files
+    pub(crate) fn toggle_syntax_highlighting(&mut self) {
+        self.syntax_highlighting = !self.syntax_highlighting;
+        self.status = Some(if self.syntax_highlighting {
+            "Syntax highlighting on".to_owned()
         } else {
-            String::new()
-        };
-        let author = self.make_signature(commit.author()?)?;
-        let committer = self.make_signature(commit.committer()?)?;
-        let parents = commit
-            .parent_ids()
-            .map(|id| {
-                let parent_commit = self.repo.find_commit(id)?;
-                let msg = parent_commit.message()?.title.to_string().trim().to_owned();
-                Ok((id.into(), id.shorten_or_id(), msg))
-            })
-            .collect::<Result<Vec<_>, anyhow::Error>>()?;
-        let diff_parent = match self.compute_diff_commit(commit) {
-            Ok(d) => d,
-            // TODO this is a bit of a hack, but it allows us to separate error domains
-            Err(e) => Diff {
-                files: vec![(
-                    FileModificationKind::Deletion,
-                    "ERROR".to_owned(),
-                    format!("error: {e:?}"),
-                )],
-            },
+            "Syntax highlighting off".to_owned()
+        });
+        self.invalidate_details();
+    }
+    /// Switches to the next diff algorithm
+    pub(crate) fn cycle_diff_algorithm(&mut self) {
+        let algorithm = &mut self.diff_options.algorithm;
+        *algorithm = match algorithm {
+            Algorithm::Myers => Algorithm::MyersMinimal,
+            Algorithm::MyersMinimal => Algorithm::Histogram,
+            Algorithm::Histogram => Algorithm::Myers,
         };
-        let commit_detail = CommitDetail {
-            author,
-            committer,
-            parents,
-            title,
-            msg_detail,
-            diff_parent,
-            id,
+        self.status = Some(format!("Diff algorithm: {}", algorithm_name(*algorithm)));
+        self.invalidate_details();
+    }
+    /// Changes the number of unchanged lines shown around changes
+    pub(crate) fn change_diff_context(&mut self, more: bool) {
+        let context = &mut self.diff_options.context;
+        *context = if more {
+            context.saturating_add(1)
+        } else {
+            context.saturating_sub(1)
         };
-        Ok(Some(Detail::CommitDetail(commit_detail)))
-    }
-    fn compute_diff_worktree_to_index(&self) -> Result<Diff, anyhow::Error> {
-        let iter = self
-            .repo
-            .status(gix::progress::Discard)?
-            .index_worktree_rewrites(None)
-            .index_worktree_submodules(gix::status::Submodule::AsConfigured { check_dirty: true })
-            .index_worktree_options_mut(|opts| {
-                opts.dirwalk_options = None;
-            })
-            .into_index_worktree_iter(Vec::new())?;
-        let mut files = iter
-            .map(|v| match v {
-                Ok(gix::status::index_worktree::Item::Modification {
-                    entry, rela_path, ..
-                }) => {
-                    let worktree = self
-                        .repo
-                        .worktree()
-                        .ok_or_else(|| anyhow::anyhow!("Repo has no worktree"))?;
-                    let in_worktree = std::fs::read(worktree.base().join(rela_path.to_string()))
-                        .context(format!("loading file {rela_path}"))?;
-
-                    let obj = self
-                        .repo
-                        .find_object(entry.id)
-                        .context(format!("finding object {}", entry.id))?;
-
-                    let interner = gix::diff::blob::intern::InternedInput::new(
-                        obj.data.as_slice(),
-                        in_worktree.as_slice(),
-                    );
-                    let diff_str_raw = gix::diff::blob::diff(
-                        gix::diff::blob::Algorithm::Myers,
-                        &interner,
-                        UnifiedDiff::new(
-                            &interner,
-                            ConsumeBinaryHunk::new(String::new(), "\n"),
-                            ContextSize::symmetrical(3),
-                        ),
-                    )
-                    .unwrap();
-                    let diff_str_raw = format!("{diff_str_raw}\nworktree to {}", entry.id);
-                    Ok::<_, anyhow::Error>((
-                        FileModificationKind::Modification,
-                        format!("{}", rela_path),
-                        diff_str_raw,
-                    ))
-                }
-                Ok(gix::status::index_worktree::Item::DirectoryContents { entry, .. }) => Ok((
-                    FileModificationKind::Addition,
-                    format!("{}", entry.rela_path),
-                    "New file".to_owned(),
-                )),
-                Ok(gix::status::index_worktree::Item::Rewrite { dirwalk_entry, .. }) => Ok((
-                    FileModificationKind::Modification,
-                    format!("{}", dirwalk_entry.rela_path),
-                    "...".to_owned(),
-                )),
-                Err(e) => Ok((
-                    FileModificationKind::Modification,
-                    format!("ERR"),
-                    format!("error: {e:?}"),
-                )),
-            })
-            .map(|res| match res {
-                Ok(v) => v,
-                Err(e) => (
-                    FileModificationKind::Modification,
-                    format!("ERR"),
-                    format!("error: {e:?}"),
-                ),
-            })
+        self.status = Some(format!("Lines of context: {context}"));
+        self.invalidate_details();
+    }
+    /// Switches to the next way of ignoring whitespace within lines
+    pub(crate) fn cycle_ignore_space(&mut self) {
+        let ignore_space = &mut self.diff_options.ignore_space;
+        *ignore_space = ignore_space.next();
+        self.status = Some(format!("Diffs are {ignore_space}"));
+        self.invalidate_details();
+    }
+    /// Toggles ignoring changes whose lines are all blank
+    pub(crate) fn toggle_ignore_blank_lines(&mut self) {
+        let ignore_blank_lines = &mut self.diff_options.ignore_blank_lines;
+        *ignore_blank_lines = !*ignore_blank_lines;
+        self.status = Some(if *ignore_blank_lines {
+            "Ignoring changes of blank lines".to_owned()
+        } else {
+            "Showing changes of blank lines".to_owned()
+        });
+        self.invalidate_details();
+    }
+    /// Drops the details, so that they are computed and rendered again with changed settings
+    pub(crate) fn invalidate_details(&mut self) {
+        self.details_cache.clear();
+        self.details_requested.clear();
+        // Details that are still being computed with the old settings are dropped as well
+        self.details_generation += 1;
+    }
+    /// Makes the rendered diffs lay out their lines anew when they are next shown
+    pub(crate) fn invalidate_layout(&mut self) {
+        for cached in self.details_cache.values_mut() {
+            cached.rendered.layout = None;
+        }
+    }
+    /// Asks the worker for the details of the selected commit, as well as the ones of its
+    /// neighbours, so that they are ready when the selection moves
+    fn request_details(&mut self) {
+        let idx = self.selection_idx;
+        self.commits_shallow();
+        let selected = self.commits_shallow_cached.get(idx).map(|cmt| cmt.id);
+        if selected != self.details_selected {
+            self.details_selected = selected;
+            // Local changes may have changed since they were last shown
+            if let Some(id @ (ShallowId::Worktree | ShallowId::Index)) = selected {
+                self.details_cache.remove(&id);
+            }
+        }
+        let commits = &self.commits_shallow_cached;
+        let wanted = [Some(idx), idx.checked_sub(1), Some(idx + 1)]
+            .into_iter()
+            .flatten()
+            .filter_map(|idx| commits.get(idx))
+            .map(|cmt| cmt.id)
+            .filter(|id| !self.details_cache.contains(id))
             .collect::<Vec<_>>();
-        files.sort_by_cached_key(|(_, path, _diff)| path.clone());
-        return Ok(Diff { files });
-    }
-    fn compute_diff_index_to_commit(&self) -> Result<Diff, anyhow::Error> {
-        let iter = self
-            .repo
-            .status(gix::progress::Discard)?
-            .index_worktree_rewrites(None)
-            .index_worktree_submodules(gix::status::Submodule::AsConfigured { check_dirty: true })
-            .untracked_files(gix::status::UntrackedFiles::Collapsed)
-            .index_worktree_options_mut(|opts| {
-                opts.dirwalk_options = None;
-            })
-            .into_iter(Vec::new())?;
-
-        let mut files = Vec::new();
-
-        for v in iter {
-            let gix::status::Item::TreeIndex(change) = v? else {
-                continue;
-            };
-            let file = match change {
-                gix::diff::index::ChangeRef::Addition {
-                    location, id: _, ..
-                } => (
-                    FileModificationKind::Addition,
-                    format!("{location}"),
-                    "...".to_owned(),
-                ),
-                gix::diff::index::ChangeRef::Deletion {
-                    location, id: _, ..
-                } => (
-                    FileModificationKind::Deletion,
-                    format!("{location}"),
-                    "...".to_owned(),
-                ),
-
-                gix::diff::index::ChangeRef::Rewrite {
-                    location,
-                    source_id: previous_id,
-                    id,
-                    ..
+        if wanted.iter().all(|id| self.details_requested.contains(id)) {
+            return;
+        }
+        self.workers.request_details(
+            wanted.clone(),
+            self.detail_params(),
+            self.details_generation,
+        );
+        self.details_requested = wanted;
+    }
+    /// Whether the worker threads are computing something that is to be shown
+    pub(crate) fn loading(&self) -> bool {
+        self.commits_shallow_requested.is_some() || !self.details_requested.is_empty()
+    }
+    /// Takes in the results of the worker threads, dropping those that are outdated
+    pub(crate) fn receive_worker_results(&mut self) {
+        while let Some(result) = self.workers.try_recv() {
+            match result {
+                JobResult::Log { generation, log } => {
+                    if self.commits_shallow_requested != Some(generation) {
+                        continue;
+                    }
+                    self.commits_shallow_requested = None;
+                    match log {
+                        Ok(log) => {
+                            self.commits_shallow_cached.extend(log.commits);
+                            self.commits_walk_exhausted = log.walk.is_none();
+                            self.log_walk = log.walk;
+                            self.worktree_index_changed_cached = log.worktree_index_changed;
+                        }
+                        Err(e) => {
+                            // Don't retry over and over again
+                            self.commits_walk_exhausted = true;
+                            self.status = Some(format!("error: {e}"));
+                        }
+                    }
                 }
-                | gix::diff::index::ChangeRef::Modification {
-                    location,
-                    previous_id,
+                JobResult::Detail {
+                    generation,
                     id,
-                    ..
+                    detail,
+                    rendered,
                 } => {
-                    // TODO don't use unwrap here but return dedicated ERR item
-                    let prev_obj = self.repo.find_object(&*previous_id.to_owned()).unwrap();
-                    let now_obj = self.repo.find_object(&*id.to_owned()).unwrap();
-
-                    let interner = gix::diff::blob::intern::InternedInput::new(
-                        prev_obj.data.as_slice(),
-                        now_obj.data.as_slice(),
-                    );
-                    let diff_str_raw = gix::diff::blob::diff(
-                        gix::diff::blob::Algorithm::Myers,
-                        &interner,
-                        UnifiedDiff::new(
-                            &interner,
-                            ConsumeBinaryHunk::new(String::new(), "\n"),
-                            ContextSize::symmetrical(3),
-                        ),
-                    )
-                    .unwrap();
-                    (
-                        FileModificationKind::Modification,
-                        format!("{location}"),
-                        diff_str_raw,
-                    )
+                    if generation != self.details_generation {
+                        continue;
+                    }
+                    self.details_requested.retain(|requested| *requested != id);
+                    self.details_cache
+                        .insert(id, CachedDetail { detail, rendered });
                 }
-            };
-            files.push(file);
+            }
         }
-        files.sort_by_cached_key(|(_, path, _diff)| path.clone());
-        return Ok(Diff { files });
     }
-    fn compute_diff_commit(&self, commit: gix::Commit<'_>) -> Result<Diff, anyhow::Error> {
-        let parent_tree = if let Some(parent_id) = commit.parent_ids().next() {
-            let parent = self.repo.find_commit(parent_id)?;
-            parent.tree()?
-        } else {
-            // No parent for commit, it's a root commit
-            self.repo.empty_tree()
-        };
-        let diff_options = None;
-        let diff_changes =
-            self.repo
-                .diff_tree_to_tree(&parent_tree, &commit.tree()?, diff_options)?;
-        let mut files = diff_changes
-            .iter()
-            .map(|chg| {
-                let (kind, location_str, prev_id_opt, now_id_opt) = match chg {
-                    gix::diff::tree_with_rewrites::Change::Addition { location, id, .. } => {
-                        let location_str = location.to_string().trim().to_owned();
-                        (
-                            FileModificationKind::Addition,
-                            location_str,
-                            None,
-                            Some(*id),
-                        )
-                    }
-                    gix::diff::tree_with_rewrites::Change::Deletion { location, .. } => (
-                        FileModificationKind::Deletion,
-                        location.to_string().trim().to_owned(),
-                        None,
-                        None,
-                    ),
-                    gix::diff::tree_with_rewrites::Change::Modification {
-                        location,
-                        previous_id,
-                        id,
-                        ..
-                    } => {
-                        let location_str = location.to_string().trim().to_owned();
-                        (
-                            FileModificationKind::Modification,
-                            location_str,
-                            Some(*previous_id),
-                            Some(*id),
-                        )
-                    }
-                    gix::diff::tree_with_rewrites::Change::Rewrite {
-                        source_location,
-                        location,
-                        source_id,
-                        id,
-                        ..
-                    } => {
-                        let source_location_str = source_location.to_string().trim().to_owned();
-                        let location_str = location.to_string().trim().to_owned();
-                        (
-                            FileModificationKind::Rewrite(source_location_str),
-                            location_str,
-                            Some(*source_id),
-                            Some(*id),
-                        )
-                    }
-                };
-                let diff_text = if let Some(id) = now_id_opt
-                    && self.repo.find_object(id)?.kind == gix::objs::Kind::Blob
-                {
-                    let now_blob = self.repo.find_blob(id)?;
-                    let mut prev_blob = None;
-                    let interner = if let Some(prev_id) = prev_id_opt {
-                        let prev_blob_ref = prev_blob.insert(self.repo.find_blob(prev_id)?);
-
-                        gix::diff::blob::intern::InternedInput::new(
-                            prev_blob_ref.data.as_slice(),
-                            now_blob.data.as_slice(),
-                        )
-                    } else {
-                        gix::diff::blob::intern::InternedInput::new(
-                            b"".as_slice(),
-                            now_blob.data.as_slice(),
-                        )
-                    };
-
-                    let diff_str_raw = gix::diff::blob::diff(
-                        gix::diff::blob::Algorithm::Myers,
-                        &interner,
-                        UnifiedDiff::new(
-                            &interner,
-                            ConsumeBinaryHunk::new(String::new(), "\n"),
-                            ContextSize::symmetrical(3),
-                        ),
-                    )?;
-                    diff_str_raw
-                } else {
-                    String::new()
-                };
-
-                Ok((kind, location_str, diff_text))
-            })
-            .collect::<Result<Vec<_>, anyhow::Error>>()?;
-        files.sort_by_cached_key(|f| f.1.clone());
-        Ok(Diff { files })
+    pub(crate) fn path_limit(&self) -> Option<PathLimit> {
+        (!self.paths.is_empty()).then(|| PathLimit {
+            paths: self.paths.clone(),
+            follow: self.follow,
+        })
+    }
+    /// Resolves the revisions given on the command line to the commits to start the walk
+    /// from, and to the commits whose ancestors should be hidden
+    pub(crate) fn resolve_revisions(
+        &self,
+    ) -> Result<(Vec<ObjectId>, Vec<ObjectId>), anyhow::Error> {
+        resolve_revisions(&self.repo, &self.revisions, &self.ref_prefixes)
     }
     pub(crate) fn compute_id_to_refs_map(&mut self) {
         let refs_res = self.repo.refs.iter();
@@ -562,10 +478,810 @@ impl State {
                 res.entry(id.into()).or_insert_with(Vec::new).push(ref_);
             }
         }
-        self.id_to_refs_map_cached = res;
+        self.id_to_refs_map_cached = Arc::new(res);
     }
+    /// Throws away the log and the details, so that they are computed anew
     pub(crate) fn invalidate_caches(&mut self) {
-        self.commits_shallow_cached = None;
-        self.selected_commit_cached = None;
+        self.commits_shallow_cached.clear();
+        self.commits_shallow_requested = None;
+        self.commits_walk_exhausted = false;
+        self.log_walk = None;
+        self.invalidate_details();
     }
 }
+
+/// What the log is computed from, so that it can be computed on a worker thread
+pub(crate) struct LogParams {
+    pub(crate) commit_order: CommitOrder,
+    pub(crate) revisions: Vec<String>,
+    pub(crate) ref_prefixes: Vec<&'static str>,
+    pub(crate) path_limit: Option<PathLimit>,
+    /// Up to which length to extend the log at least
+    pub(crate) budget: usize,
+    pub(crate) id_to_refs_map: Arc<HashMap<ObjectId, Vec<Reference>>>,
+    /// Whether there are worktree and index changes, if already known
+    pub(crate) worktree_index_changed: Option<(bool, bool)>,
+    pub(crate) show_untracked: bool,
+}
+
+/// What the details of entries of the log are computed and rendered with, besides the
+/// entries themselves
+#[derive(Clone)]
+pub(crate) struct DetailParams {
+    /// Whether to show untracked files in the worktree changes
+    pub(crate) show_untracked: bool,
+    pub(crate) merge_diff: MergeDiff,
+    pub(crate) diff_options: DiffOptions,
+    pub(crate) collapse_deletions: bool,
+    pub(crate) word_diff: WordDiff,
+    pub(crate) syntax_highlighting: bool,
+}
+
+/// How diffs of files are computed
+#[derive(Clone, Copy)]
+pub(crate) struct DiffOptions {
+    pub(crate) algorithm: Algorithm,
+    /// Number of unchanged lines to show around changes
+    pub(crate) context: u32,
+    pub(crate) ignore_space: IgnoreSpace,
+    /// Like git's `--ignore-blank-lines`, ignore changes whose lines are all blank
+    pub(crate) ignore_blank_lines: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            algorithm: Algorithm::Myers,
+            context: 3,
+            ignore_space: IgnoreSpace::None,
+            ignore_blank_lines: false,
+        }
+    }
+}
+
+/// The name of the diff algorithm, the way git's `diff.algorithm` calls it
+pub(crate) fn algorithm_name(algorithm: Algorithm) -> &'static str {
+    match algorithm {
+        Algorithm::Myers => "myers",
+        Algorithm::MyersMinimal => "minimal",
+        Algorithm::Histogram => "histogram",
+    }
+}
+
+/// Parses the diff algorithm like git does, which has patience as a variant of histogram
+pub(crate) fn parse_algorithm(name: &str) -> Result<Algorithm, String> {
+    match name {
+        "myers" | "default" => Ok(Algorithm::Myers),
+        "minimal" => Ok(Algorithm::MyersMinimal),
+        "histogram" | "patience" => Ok(Algorithm::Histogram),
+        _ => Err(format!(
+            "unknown diff algorithm '{name}', expected myers, minimal, patience or histogram"
+        )),
+    }
+}
+
+/// The walk through the history that the log is extended from, so that it can be resumed
+/// when more commits are needed
+pub(crate) struct LogWalk {
+    walk: CommitWalk,
+    graph: GraphBuilder,
+    /// Number of entries of the log so far
+    len: usize,
+}
+
+/// The entries that a walk added to the log
+pub(crate) struct Log {
+    pub(crate) commits: Vec<CommitShallow>,
+    /// The walk to continue with, or `None` if it reached the end of the history
+    pub(crate) walk: Option<LogWalk>,
+    pub(crate) worktree_index_changed: Option<(bool, bool)>,
+}
+
+fn make_signature(sig: SignatureRef<'_>) -> Result<Signature, anyhow::Error> {
+    Ok(Signature {
+        author_name: sig.name.to_string().trim().to_owned(),
+        author_email: sig.email.to_string().trim().to_owned(),
+        time: sig.time()?.format(gix::date::time::format::ISO8601)?,
+    })
+}
+
+/// Number of commits to walk at most per request, so that a walk limited to paths that
+/// rarely change returns the commits found so far instead of going through all of history
+const MAX_WALKED_PER_REQUEST: usize = 2000;
+
+/// Continues the walk until the log is longer than the budget, or starts a new walk if
+/// there is none yet
+pub(crate) fn walk_commits_shallow(
+    repo: &Repository,
+    params: &LogParams,
+    walk: Option<LogWalk>,
+) -> Result<Log, anyhow::Error> {
+    let mut res = Vec::new();
+    let mut worktree_index_changed = params.worktree_index_changed;
+    let mut log_walk = match walk {
+        Some(walk) => walk,
+        None => start_walk(repo, params, &mut res, &mut worktree_index_changed)?,
+    };
+    let LogWalk { walk, graph, len } = &mut log_walk;
+
+    let mut walked_count = 0;
+    while *len <= params.budget && walked_count < MAX_WALKED_PER_REQUEST {
+        walked_count += 1;
+        let Some(walked) = walk.next(repo)? else {
+            return Ok(Log {
+                commits: res,
+                walk: None,
+                worktree_index_changed,
+            });
+        };
+        if !walked.shown {
+            graph.skip(walked.id, walked.parents.first().copied());
+            continue;
+        }
+        let commit = repo.find_commit(walked.id)?;
+        let msg = commit.message()?;
+        let title = msg.title.to_string();
+        let body = msg.body.map(|body| body.to_string()).unwrap_or_default();
+        let refs_id = params.id_to_refs_map.get(&commit.id);
+        let refs = if let Some(refs_id) = refs_id {
+            refs_id
+                .iter()
+                .map(|ref_| format!("{}", ref_.name.as_bstr()).trim().to_owned())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        res.push(CommitShallow {
+            id: ShallowId::CommitId(commit.id, commit.short_id()?),
+            commit: title.trim().to_string(),
+            body,
+            refs,
+            signature: make_signature(commit.author()?)?,
+            graph: graph.push(Some(commit.id), &walked.parents),
+        });
+        *len += 1;
+    }
+    Ok(Log {
+        commits: res,
+        walk: Some(log_walk),
+        worktree_index_changed,
+    })
+}
+
+/// Starts the walk for the log, adding the entries for local changes in front
+fn start_walk(
+    repo: &Repository,
+    params: &LogParams,
+    res: &mut Vec<CommitShallow>,
+    worktree_index_changed: &mut Option<(bool, bool)>,
+) -> Result<LogWalk, anyhow::Error> {
+    let (tips, hidden) = resolve_revisions(repo, &params.revisions, &params.ref_prefixes)?;
+
+    let head_id = repo.head_id().ok().map(|id| id.detach());
+    // Local changes are only relevant if we are looking at the history of HEAD
+    let show_local_changes =
+        head_id.is_some_and(|head_id| tips.contains(&head_id)) && params.path_limit.is_none();
+    let (worktree_changes, index_changes) = if show_local_changes {
+        *match worktree_index_changed {
+            Some(changed) => changed,
+            None => worktree_index_changed
+                .insert(has_worktree_index_changes(repo, params.show_untracked)?),
+        }
+    } else {
+        (false, false)
+    };
+    let head_parents = head_id.into_iter().collect::<Vec<_>>();
+
+    let mut graph = GraphBuilder::default();
+
+    if worktree_changes {
+        res.push(CommitShallow {
+            id: ShallowId::Worktree,
+            commit: "Worktree changes, not in index".to_string(),
+            body: String::new(),
+            refs: Vec::new(),
+            signature: Signature {
+                author_name: String::new(),
+                author_email: String::new(),
+                time: String::new(),
+            },
+            graph: graph.push(None, &head_parents),
+        });
+    }
+    if index_changes {
+        res.push(CommitShallow {
+            id: ShallowId::Index,
+            commit: "Index changes, not in a commit".to_string(),
+            body: String::new(),
+            refs: Vec::new(),
+            signature: Signature {
+                author_name: String::new(),
+                author_email: String::new(),
+                time: String::new(),
+            },
+            graph: graph.push(None, &head_parents),
+        });
+    }
+
+    let walk = CommitWalk::new(
+        repo,
+        params.commit_order,
+        tips,
+        hidden,
+        params.path_limit.clone(),
+    )?;
+    Ok(LogWalk {
+        walk,
+        graph,
+        len: res.len(),
+    })
+}
+
+/// Resolves the revisions given on the command line to the commits to start the walk
+/// from, and to the commits whose ancestors should be hidden
+fn resolve_revisions(
+    repo: &Repository,
+    revisions: &[String],
+    ref_prefixes: &[&str],
+) -> Result<(Vec<ObjectId>, Vec<ObjectId>), anyhow::Error> {
+    use gix::revision::plumbing::Spec;
+
+    let peel = |id: ObjectId| -> Result<ObjectId, anyhow::Error> {
+        Ok(repo.find_object(id)?.peel_to_commit()?.id)
+    };
+    let mut tips = Vec::new();
+    let mut hidden = Vec::new();
+    for revision in revisions {
+        let spec = repo
+            .rev_parse(revision.as_str())
+            .with_context(|| format!("parsing revision '{revision}'"))?;
+        match spec.detach() {
+            Spec::Include(id) => tips.push(peel(id)?),
+            Spec::Exclude(id) => hidden.push(peel(id)?),
+            Spec::Range { from, to } => {
+                hidden.push(peel(from)?);
+                tips.push(peel(to)?);
+            }
+            Spec::Merge { theirs, ours } => {
+                let (theirs, ours) = (peel(theirs)?, peel(ours)?);
+                tips.extend([theirs, ours]);
+                let bases = repo.merge_bases_many(theirs, &[ours])?;
+                hidden.extend(bases.into_iter().map(|id| id.detach()));
+            }
+            Spec::IncludeOnlyParents(id) => {
+                let commit = repo.find_commit(peel(id)?)?;
+                tips.extend(commit.parent_ids().map(|id| id.detach()));
+            }
+            Spec::ExcludeParents(id) => {
+                let commit = repo.find_commit(peel(id)?)?;
+                tips.push(commit.id);
+                hidden.extend(commit.parent_ids().map(|id| id.detach()));
+            }
+        }
+    }
+    for prefix in ref_prefixes {
+        for reference in repo.references()?.prefixed(*prefix)? {
+            let mut reference = reference.map_err(|e| anyhow::anyhow!(e))?;
+            // Like git, skip refs that don't point to commits, e.g. tags of trees
+            if let Ok(commit) = reference.peel_to_commit() {
+                tips.push(commit.id);
+            }
+        }
+    }
+    Ok((tips, hidden))
+}
+
+fn has_worktree_index_changes(
+    repo: &Repository,
+    show_untracked: bool,
+) -> Result<(bool, bool), anyhow::Error> {
+    let untracked_files = if show_untracked {
+        gix::status::UntrackedFiles::Collapsed
+    } else {
+        gix::status::UntrackedFiles::None
+    };
+    let iter = repo
+        .status(gix::progress::Discard)?
+        .index_worktree_rewrites(None)
+        .index_worktree_submodules(gix::status::Submodule::AsConfigured { check_dirty: true })
+        .untracked_files(untracked_files)
+        .into_iter(Vec::new())?;
+
+    let mut worktree_changes = false;
+    let mut index_changes = false;
+
+    for it in iter {
+        match it? {
+            gix::status::Item::IndexWorktree(_) => worktree_changes = true,
+            gix::status::Item::TreeIndex(_) => index_changes = true,
+        }
+    }
+    Ok((worktree_changes, index_changes))
+}
+
+/// Computes what to show in the diff area for the entry of the log with the given id
+pub(crate) fn compute_detail(repo: &Repository, id: ShallowId, params: &DetailParams) -> Detail {
+    let detail = match id {
+        ShallowId::CommitId(id, _prefix) => {
+            compute_commit_detail(repo, id, params).map(Detail::CommitDetail)
+        }
+        ShallowId::Worktree => {
+            compute_diff_worktree_to_index(repo, params).map(Detail::DiffTreeIndex)
+        }
+        ShallowId::Index => {
+            compute_diff_index_to_commit(repo, &params.diff_options).map(Detail::DiffIndexCommit)
+        }
+    };
+    detail.unwrap_or_else(Detail::Error)
+}
+
+fn compute_commit_detail(
+    repo: &Repository,
+    id: ObjectId,
+    params: &DetailParams,
+) -> Result<CommitDetail, anyhow::Error> {
+    let commit = repo.find_commit(id)?;
+    let msg = commit.message()?;
+    let title = msg.title.to_string().trim().to_owned();
+    let msg_detail = if let Some(body) = msg.body() {
+        body.without_trailer().to_string()
+    } else {
+        String::new()
+    };
+    let author = make_signature(commit.author()?)?;
+    let committer = make_signature(commit.committer()?)?;
+    let parents = commit
+        .parent_ids()
+        .map(|id| {
+            let parent_commit = repo.find_commit(id)?;
+            let msg = parent_commit.message()?.title.to_string().trim().to_owned();
+            Ok((id.into(), id.shorten_or_id(), msg))
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+    // Fall back to the first parent if a merge with more parents was shown before
+    let merge_diff = (parents.len() > 1).then_some(match params.merge_diff {
+        MergeDiff::Parent(idx) if idx >= parents.len() => MergeDiff::Parent(0),
+        merge_diff => merge_diff,
+    });
+    let opts = &params.diff_options;
+    let diff = match merge_diff {
+        Some(MergeDiff::Combined) => compute_combined_diff_commit(repo, &commit, opts),
+        Some(MergeDiff::Parent(idx)) => compute_diff_commit_to_parent(repo, &commit, idx, opts),
+        None => compute_diff_commit(repo, &commit, opts),
+    };
+    let diff_parent = match diff {
+        Ok(d) => d,
+        // TODO this is a bit of a hack, but it allows us to separate error domains
+        Err(e) => Diff::new(
+            vec![(
+                FileModificationKind::Deletion,
+                "ERROR".to_owned(),
+                format!("error: {e:?}"),
+            )],
+            1,
+        ),
+    };
+    Ok(CommitDetail {
+        author,
+        committer,
+        parents,
+        title,
+        msg_detail,
+        diff_parent,
+        merge_diff,
+        id,
+    })
+}
+
+fn compute_diff_worktree_to_index(
+    repo: &Repository,
+    params: &DetailParams,
+) -> Result<Diff, anyhow::Error> {
+    let opts = &params.diff_options;
+    let untracked_files = if params.show_untracked {
+        gix::status::UntrackedFiles::Files
+    } else {
+        gix::status::UntrackedFiles::None
+    };
+    let iter = repo
+        .status(gix::progress::Discard)?
+        .index_worktree_rewrites(gix::diff::Rewrites::default())
+        .index_worktree_submodules(gix::status::Submodule::AsConfigured { check_dirty: true })
+        .untracked_files(untracked_files)
+        .into_index_worktree_iter(Vec::new())?;
+    let mut files = iter
+        .map(|v| match v {
+            Ok(gix::status::index_worktree::Item::Modification {
+                entry,
+                rela_path,
+                status,
+                ..
+            }) => {
+                let (kind, now_path) = match status {
+                    EntryStatus::Change(Change::Removed) => (FileModificationKind::Deletion, None),
+                    EntryStatus::IntentToAdd => (FileModificationKind::Addition, Some(&rela_path)),
+                    _ => (FileModificationKind::Modification, Some(&rela_path)),
+                };
+                // Intent-to-add entries have no object yet
+                let prev_id = (!matches!(status, EntryStatus::IntentToAdd)).then_some(entry.id);
+                let diff_str = if entry.mode.is_submodule() {
+                    Ok(String::new())
+                } else {
+                    diff_blob_to_worktree(repo, prev_id, now_path.map(|p| p.as_ref()), opts)
+                };
+                (kind, rela_path.to_string(), diff_str)
+            }
+            Ok(gix::status::index_worktree::Item::DirectoryContents { entry, .. }) => {
+                let diff_str =
+                    read_worktree_file(repo, entry.rela_path.as_ref()).and_then(|content| {
+                        if is_binary(&content) {
+                            Ok(format!("Binary file, {} bytes", content.len()))
+                        } else {
+                            diff_data(&[], &content, opts)
+                        }
+                    });
+                (
+                    FileModificationKind::Addition,
+                    entry.rela_path.to_string(),
+                    diff_str,
+                )
+            }
+            Ok(gix::status::index_worktree::Item::Rewrite {
+                source,
+                dirwalk_entry,
+                ..
+            }) => {
+                let now_path = dirwalk_entry.rela_path.as_ref();
+                let diff_str = match &source {
+                    RewriteSource::RewriteFromIndex { source_entry, .. } => {
+                        diff_blob_to_worktree(repo, Some(source_entry.id), Some(now_path), opts)
+                    }
+                    RewriteSource::CopyFromDirectoryEntry {
+                        source_dirwalk_entry,
+                        ..
+                    } => read_worktree_file(repo, source_dirwalk_entry.rela_path.as_ref())
+                        .and_then(|source| {
+                            diff_data(&source, &read_worktree_file(repo, now_path)?, opts)
+                        }),
+                };
+                (
+                    FileModificationKind::Rewrite(source.rela_path().to_string()),
+                    now_path.to_string(),
+                    diff_str,
+                )
+            }
+            Err(e) => (
+                FileModificationKind::Modification,
+                "ERR".to_string(),
+                Err(e.into()),
+            ),
+        })
+        // An error of one file shouldn't hide the other changes
+        .map(|(kind, path, diff_str)| {
+            (
+                kind,
+                path,
+                diff_str.unwrap_or_else(|e| format!("error: {e:?}")),
+            )
+        })
+        .collect::<Vec<_>>();
+    files.sort_by_cached_key(|(_, path, _diff)| path.clone());
+    Ok(Diff::new(files, 1))
+}
+fn compute_diff_index_to_commit(
+    repo: &Repository,
+    opts: &DiffOptions,
+) -> Result<Diff, anyhow::Error> {
+    let iter = repo
+        .status(gix::progress::Discard)?
+        .index_worktree_rewrites(None)
+        .index_worktree_submodules(gix::status::Submodule::AsConfigured { check_dirty: true })
+        .untracked_files(gix::status::UntrackedFiles::Collapsed)
+        .index_worktree_options_mut(|opts| {
+            opts.dirwalk_options = None;
+        })
+        .into_iter(Vec::new())?;
+
+    let mut files = Vec::new();
+
+    for v in iter {
+        let change = match v {
+            Ok(gix::status::Item::TreeIndex(change)) => change,
+            Ok(gix::status::Item::IndexWorktree(_)) => continue,
+            Err(e) => {
+                files.push((
+                    FileModificationKind::Modification,
+                    "ERR".to_string(),
+                    format!("error: {e:?}"),
+                ));
+                continue;
+            }
+        };
+        let (kind, location, prev_id, id) = match change {
+            gix::diff::index::ChangeRef::Addition { location, id, .. } => (
+                FileModificationKind::Addition,
+                location,
+                None,
+                Some(id.into_owned()),
+            ),
+            gix::diff::index::ChangeRef::Deletion { location, id, .. } => (
+                FileModificationKind::Deletion,
+                location,
+                Some(id.into_owned()),
+                None,
+            ),
+            gix::diff::index::ChangeRef::Modification {
+                location,
+                previous_id,
+                id,
+                ..
+            } => (
+                FileModificationKind::Modification,
+                location,
+                Some(previous_id.into_owned()),
+                Some(id.into_owned()),
+            ),
+            gix::diff::index::ChangeRef::Rewrite {
+                source_location,
+                source_id,
+                location,
+                id,
+                ..
+            } => (
+                FileModificationKind::Rewrite(source_location.to_string()),
+                location,
+                Some(source_id.into_owned()),
+                Some(id.into_owned()),
+            ),
+        };
+        // A single object that can't be loaded shouldn't hide the other changes
+        let diff_text =
+            diff_blobs(repo, prev_id, id, opts).unwrap_or_else(|e| format!("error: {e:?}"));
+        files.push((kind, location.to_string(), diff_text));
+    }
+    files.sort_by_cached_key(|(_, path, _diff)| path.clone());
+    Ok(Diff::new(files, 1))
+}
+/// Computes the diff of the commit to its first parent
+pub(crate) fn compute_diff_commit(
+    repo: &Repository,
+    commit: &gix::Commit<'_>,
+    opts: &DiffOptions,
+) -> Result<Diff, anyhow::Error> {
+    compute_diff_commit_to_parent(repo, commit, 0, opts)
+}
+/// Computes the diff of the commit to the parent with the given index
+fn compute_diff_commit_to_parent(
+    repo: &Repository,
+    commit: &gix::Commit<'_>,
+    parent_idx: usize,
+    opts: &DiffOptions,
+) -> Result<Diff, anyhow::Error> {
+    let parent_tree = if let Some(parent_id) = commit.parent_ids().nth(parent_idx) {
+        let parent = repo.find_commit(parent_id)?;
+        parent.tree()?
+    } else {
+        // No parent for commit, it's a root commit
+        repo.empty_tree()
+    };
+    let diff_options = None;
+    let diff_changes = repo.diff_tree_to_tree(&parent_tree, &commit.tree()?, diff_options)?;
+    let mut files = diff_changes
+        .iter()
+        // Directories are implied by the files in them
+        .filter(|chg| !chg.entry_mode().is_tree())
+        .map(|chg| {
+            let (kind, location_str, prev_id_opt, now_id_opt) = match chg {
+                gix::diff::tree_with_rewrites::Change::Addition { location, id, .. } => {
+                    let location_str = location.to_string().trim().to_owned();
+                    (
+                        FileModificationKind::Addition,
+                        location_str,
+                        None,
+                        Some(*id),
+                    )
+                }
+                gix::diff::tree_with_rewrites::Change::Deletion { location, id, .. } => (
+                    FileModificationKind::Deletion,
+                    location.to_string().trim().to_owned(),
+                    Some(*id),
+                    None,
+                ),
+                gix::diff::tree_with_rewrites::Change::Modification {
+                    location,
+                    previous_id,
+                    id,
+                    ..
+                } => {
+                    let location_str = location.to_string().trim().to_owned();
+                    (
+                        FileModificationKind::Modification,
+                        location_str,
+                        Some(*previous_id),
+                        Some(*id),
+                    )
+                }
+                gix::diff::tree_with_rewrites::Change::Rewrite {
+                    source_location,
+                    location,
+                    source_id,
+                    id,
+                    ..
+                } => {
+                    let source_location_str = source_location.to_string().trim().to_owned();
+                    let location_str = location.to_string().trim().to_owned();
+                    (
+                        FileModificationKind::Rewrite(source_location_str),
+                        location_str,
+                        Some(*source_id),
+                        Some(*id),
+                    )
+                }
+            };
+            let diff_text = diff_blobs(repo, prev_id_opt, now_id_opt, opts)?;
+
+            Ok((kind, location_str, diff_text))
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+    files.sort_by_cached_key(|f| f.1.clone());
+    Ok(Diff::new(files, 1))
+}
+
+/// Computes the combined diff of a merge commit, for the files that differ from all parents
+fn compute_combined_diff_commit(
+    repo: &Repository,
+    commit: &gix::Commit<'_>,
+    opts: &DiffOptions,
+) -> Result<Diff, anyhow::Error> {
+    let tree = commit.tree()?;
+    // Renames would make it ambiguous which files to compare
+    let diff_options = gix::diff::Options::default().with_rewrites(None);
+    // For each parent, the changed paths with their blob in the parent
+    let mut parents_changes = Vec::new();
+    let mut result_ids = HashMap::new();
+    for parent_id in commit.parent_ids() {
+        let parent_tree = repo.find_commit(parent_id)?.tree()?;
+        let diff_changes = repo.diff_tree_to_tree(&parent_tree, &tree, diff_options)?;
+        let mut changes = HashMap::new();
+        for chg in diff_changes
+            .iter()
+            .filter(|chg| !chg.entry_mode().is_tree())
+        {
+            let (prev_id, id) = match chg {
+                gix::diff::tree_with_rewrites::Change::Addition { id, .. } => (None, Some(*id)),
+                gix::diff::tree_with_rewrites::Change::Deletion { id, .. } => (Some(*id), None),
+                gix::diff::tree_with_rewrites::Change::Modification {
+                    previous_id, id, ..
+                } => (Some(*previous_id), Some(*id)),
+                gix::diff::tree_with_rewrites::Change::Rewrite { .. } => continue,
+            };
+            let location = chg.location().to_string();
+            result_ids.insert(location.clone(), id);
+            changes.insert(location, prev_id);
+        }
+        parents_changes.push(changes);
+    }
+
+    let mut files = Vec::new();
+    for (location, id) in result_ids {
+        let Some(prev_ids) = parents_changes
+            .iter()
+            .map(|changes| changes.get(&location).copied())
+            .collect::<Option<Vec<_>>>()
+        else {
+            // The merge took the file from one of the parents
+            continue;
+        };
+        let kind = if id.is_none() {
+            FileModificationKind::Deletion
+        } else if prev_ids.iter().all(Option::is_none) {
+            FileModificationKind::Addition
+        } else {
+            FileModificationKind::Modification
+        };
+        let ids = prev_ids.into_iter().chain([id]).collect::<Vec<_>>();
+        let Some(mut blobs) = load_blobs(repo, &ids)? else {
+            continue;
+        };
+        let now_data = blobs.pop().unwrap_or_default();
+        let datas = blobs.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        let diff_str = combined_diff(&datas, &now_data, opts);
+        files.push((kind, location, diff_str));
+    }
+    files.sort_by_cached_key(|f| f.1.clone());
+    Ok(Diff::new(files, parents_changes.len()))
+}
+
+/// Computes the unified diff between two blobs, a missing side counts as empty
+fn diff_blobs(
+    repo: &Repository,
+    prev_id: Option<ObjectId>,
+    id: Option<ObjectId>,
+    opts: &DiffOptions,
+) -> Result<String, anyhow::Error> {
+    let Some(blobs) = load_blobs(repo, &[prev_id, id])? else {
+        return Ok(String::new());
+    };
+    diff_data(&blobs[0], &blobs[1], opts)
+}
+
+/// Loads the contents of the blobs, a missing one counts as empty.
+///
+/// Returns `None` if one of them is a submodule, which is a commit without content that
+/// could be diffed.
+fn load_blobs(
+    repo: &Repository,
+    ids: &[Option<ObjectId>],
+) -> Result<Option<Vec<Vec<u8>>>, anyhow::Error> {
+    let mut blobs = Vec::with_capacity(ids.len());
+    for id in ids {
+        let Some(id) = id else {
+            blobs.push(Vec::new());
+            continue;
+        };
+        let obj = repo.find_object(*id)?;
+        if obj.kind != gix::objs::Kind::Blob {
+            return Ok(None);
+        }
+        blobs.push(obj.detach().data);
+    }
+    Ok(Some(blobs))
+}
+
+/// Computes the unified diff between a blob and a file in the worktree, a missing side counts
+/// as empty
+fn diff_blob_to_worktree(
+    repo: &Repository,
+    prev_id: Option<ObjectId>,
+    rela_path: Option<&BStr>,
+    opts: &DiffOptions,
+) -> Result<String, anyhow::Error> {
+    let prev_obj = prev_id
+        .map(|id| {
+            repo.find_object(id)
+                .with_context(|| format!("finding object {id}"))
+        })
+        .transpose()?;
+    let now_data = rela_path
+        .map(|rela_path| read_worktree_file(repo, rela_path))
+        .transpose()?;
+    let prev_data = prev_obj.as_ref().map_or(&[][..], |obj| &obj.data[..]);
+    diff_data(prev_data, now_data.as_deref().unwrap_or_default(), opts)
+}
+
+fn diff_data(
+    prev_data: &[u8],
+    now_data: &[u8],
+    opts: &DiffOptions,
+) -> Result<String, anyhow::Error> {
+    let interner = gix::diff::blob::intern::InternedInput::new(prev_data, now_data);
+    let mut unified_diff = UnifiedDiff::new(
+        &interner,
+        ConsumeBinaryHunk::new(String::new(), "\n"),
+        ContextSize::symmetrical(opts.context),
+    );
+    for (before, after) in line_changes(prev_data, now_data, opts) {
+        unified_diff.process_change(before, after);
+    }
+    Ok(unified_diff.finish()?)
+}
+
+/// Reads a file of the worktree the way git would store it, so symlinks as their target
+fn read_worktree_file(repo: &Repository, rela_path: &BStr) -> Result<Vec<u8>, anyhow::Error> {
+    let worktree = repo
+        .worktree()
+        .ok_or_else(|| anyhow::anyhow!("Repo has no worktree"))?;
+    let path = worktree.base().join(gix::path::from_bstr(rela_path));
+    let content = if path.is_symlink() {
+        let target = std::fs::read_link(&path)?;
+        gix::path::into_bstr(target).to_vec()
+    } else {
+        std::fs::read(&path)?
+    };
+    Ok(content)
+}
+
+/// Whether the content looks binary, using the same heuristic as git
+fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(8000).any(|b| *b == 0)
+}
@@ -0,0 +1,88 @@
+use ratatui::{
+    crossterm::event::{MouseButton, MouseEvent, MouseEventKind},
+    layout::{Position, Rect},
+};
+
+use crate::{State, draw::split_diff_area, file_list::listed_files};
+
+/// Rows to scroll per step of the mouse wheel
+const WHEEL_STEP: usize = 3;
+/// The log and the diff area can't be dragged smaller than this
+const MIN_AREA_HEIGHT: u16 = 3;
+
+/// The row within the bordered area, if the position is inside its borders
+fn inner_row(area: Rect, position: Position) -> Option<usize> {
+    let inner = area.inner(ratatui::layout::Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+    inner
+        .contains(position)
+        .then(|| (position.y - inner.y) as usize)
+}
+
+impl State {
+    pub(crate) fn handle_mouse(&mut self, mouse: MouseEvent) {
+        let position = Position::new(mouse.column, mouse.row);
+        let (log_area, diff_area) = (self.last_log_area, self.last_diff_area);
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                // The bottom border of the log and the top border of the diff area meet
+                if position.y + 1 == log_area.bottom() || position.y == diff_area.y {
+                    self.dragging_divider = true;
+                } else if let Some(row) = inner_row(log_area, position) {
+                    self.click_log_row(row);
+                } else if let Some(row) = inner_row(split_diff_area(diff_area)[1], position) {
+                    self.click_file_row(row);
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.dragging_divider => {
+                let total = log_area.height + diff_area.height;
+                let log_height = (position.y + 1).saturating_sub(log_area.y);
+                self.log_height = Some(
+                    log_height
+                        .max(MIN_AREA_HEIGHT)
+                        .min(total.saturating_sub(MIN_AREA_HEIGHT)),
+                );
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.dragging_divider = false,
+            MouseEventKind::ScrollDown if log_area.contains(position) => {
+                let max_scroll = self.commits_shallow_cached.len().saturating_sub(1);
+                self.commits_scroll_idx = (self.commits_scroll_idx + WHEEL_STEP).min(max_scroll);
+            }
+            MouseEventKind::ScrollUp if log_area.contains(position) => {
+                self.commits_scroll_idx = self.commits_scroll_idx.saturating_sub(WHEEL_STEP);
+            }
+            MouseEventKind::ScrollDown if diff_area.contains(position) => {
+                self.diff_scroll_idx += WHEEL_STEP;
+            }
+            MouseEventKind::ScrollUp if diff_area.contains(position) => {
+                self.diff_scroll_idx = self.diff_scroll_idx.saturating_sub(WHEEL_STEP);
+            }
+            _ => (),
+        }
+    }
+    /// Selects the commit shown in the row of the log
+    fn click_log_row(&mut self, row: usize) {
+        let idx = self.commits_scroll_idx + row;
+        self.files_focused = false;
+        if idx < self.commits_shallow_cached.len() {
+            self.select_commit(idx);
+        }
+    }
+    /// Scrolls the diff area to the file shown in the row of the file list
+    fn click_file_row(&mut self, row: usize) {
+        let filter = self.file_filter.clone();
+        let files_scroll = self.last_files_scroll;
+        let Some(rendered_diff) = self.rendered_diff() else {
+            return;
+        };
+        let listed = listed_files(rendered_diff, filter.as_deref());
+        let Some(file_idx) = listed.get(files_scroll + row).copied() else {
+            return;
+        };
+        self.files_focused = true;
+        self.file_cursor = Some(file_idx);
+        self.jump_to_file_cursor();
+    }
+}
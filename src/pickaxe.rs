@@ -0,0 +1,165 @@
+use std::collections::HashSet;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+    mpsc::{Receiver, Sender},
+};
+
+use gix::{ObjectId, Repository};
+use regex::Regex;
+
+use crate::{
+    model::{Diff, DiffOptions, compute_diff_commit},
+    walk::{CommitOrder, CommitWalk, PathLimit},
+};
+
+/// How often the background thread reports how far it got
+const PROGRESS_INTERVAL: usize = 100;
+
+pub(crate) enum PickaxeKind {
+    /// Commits changing the number of occurrences of a string, like `git log -S`
+    Occurrences(String),
+    /// Commits adding or removing lines matching a regex, like `git log -G`
+    LinesMatching(Regex),
+}
+
+impl PickaxeKind {
+    fn matches(&self, diff: &Diff) -> bool {
+        diff.files.iter().any(|(_kind, _path, diff_text, _stat)| {
+            let changed = |prefix| {
+                diff_text
+                    .lines()
+                    .filter(move |line| !line.starts_with("@@"))
+                    .filter_map(move |line| line.strip_prefix(prefix))
+            };
+            match self {
+                PickaxeKind::Occurrences(needle) => {
+                    let count = |lines: &mut dyn Iterator<Item = &str>| {
+                        lines
+                            .map(|line| line.matches(needle.as_str()).count())
+                            .sum::<usize>()
+                    };
+                    count(&mut changed('-')) != count(&mut changed('+'))
+                }
+                PickaxeKind::LinesMatching(regex) => changed('-')
+                    .chain(changed('+'))
+                    .any(|line| regex.is_match(line)),
+            }
+        })
+    }
+}
+
+enum PickaxeMsg {
+    Match(ObjectId),
+    Progress(usize),
+    Done,
+    Error(anyhow::Error),
+}
+
+/// A search through the diffs of commits, running on a background thread
+pub(crate) struct Pickaxe {
+    pub(crate) matches: HashSet<ObjectId>,
+    /// Number of commits that have been looked at so far
+    pub(crate) searched: usize,
+    pub(crate) done: bool,
+    receiver: Receiver<PickaxeMsg>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Pickaxe {
+    /// Starts searching the history that the walk with the given parameters yields
+    pub(crate) fn spawn(
+        repo: Repository,
+        order: CommitOrder,
+        (tips, hidden): (Vec<ObjectId>, Vec<ObjectId>),
+        path_limit: Option<PathLimit>,
+        kind: PickaxeKind,
+    ) -> Pickaxe {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let cancel_thread = cancel.clone();
+        std::thread::spawn(move || {
+            let res = search_history(
+                &repo,
+                CommitWalk::new(&repo, order, tips, hidden, path_limit),
+                &kind,
+                &sender,
+                &cancel_thread,
+            );
+            let msg = match res {
+                Ok(()) => PickaxeMsg::Done,
+                Err(e) => PickaxeMsg::Error(e),
+            };
+            // If the receiver is gone, the search was cancelled, so nothing to do
+            let _ = sender.send(msg);
+        });
+        Pickaxe {
+            matches: HashSet::new(),
+            searched: 0,
+            done: false,
+            receiver,
+            cancel,
+        }
+    }
+    /// Takes in what the background thread has found so far
+    pub(crate) fn poll(&mut self) -> Result<(), anyhow::Error> {
+        while let Ok(msg) = self.receiver.try_recv() {
+            match msg {
+                PickaxeMsg::Match(id) => {
+                    self.matches.insert(id);
+                }
+                PickaxeMsg::Progress(searched) => self.searched = searched,
+                PickaxeMsg::Done => self.done = true,
+                PickaxeMsg::Error(e) => {
+                    self.done = true;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+    pub(crate) fn cancel(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.done = true;
+    }
+}
+
+impl Drop for Pickaxe {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+fn search_history(
+    repo: &Repository,
+    walk: Result<CommitWalk, anyhow::Error>,
+    kind: &PickaxeKind,
+    sender: &Sender<PickaxeMsg>,
+    cancel: &AtomicBool,
+) -> Result<(), anyhow::Error> {
+    let mut walk = walk?;
+    let mut searched = 0;
+    while let Some(walked) = walk.next(repo)? {
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        if !walked.shown {
+            continue;
+        }
+        searched += 1;
+        let commit = repo.find_commit(walked.id)?;
+        // Like git, don't look at the diffs of merges
+        if commit.parent_ids().count() <= 1 {
+            let diff = compute_diff_commit(repo, &commit, &DiffOptions::default())?;
+            if kind.matches(&diff) && sender.send(PickaxeMsg::Match(walked.id)).is_err() {
+                return Ok(());
+            }
+        }
+        if searched % PROGRESS_INTERVAL == 0 && sender.send(PickaxeMsg::Progress(searched)).is_err()
+        {
+            return Ok(());
+        }
+    }
+    let _ = sender.send(PickaxeMsg::Progress(searched));
+    Ok(())
+}
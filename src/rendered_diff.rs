@@ -0,0 +1,448 @@
+use std::collections::HashSet;
+
+use gix::diff::blob::Algorithm;
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::word_diff::{WordDiff, highlight_block, word_diff_block};
+
+/// Each side of a side by side diff needs at least this many columns
+const MIN_SIDE_WIDTH: u16 = 40;
+
+/// Columns that a tab advances to the next multiple of in side by side diffs
+const TAB_WIDTH: usize = 8;
+
+/// How the hunks of the diff area are laid out
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum DiffLayout {
+    /// The lines one after another, optionally with the old and new line numbers in front
+    Unified { line_numbers: bool },
+    /// The old and the new lines next to each other, filling the given number of columns
+    SideBySide(u16),
+}
+
+impl DiffLayout {
+    /// The side by side layout for the given width, unless the sides wouldn't fit
+    pub(crate) fn side_by_side(width: u16) -> Option<DiffLayout> {
+        (width >= 2 * MIN_SIDE_WIDTH + 3).then_some(DiffLayout::SideBySide(width))
+    }
+}
+
+/// The details of an entry of the log, laid out for the diff area
+#[derive(Clone)]
+pub(crate) struct RenderedDiff {
+    pub(crate) files: Vec<RenderedFile>,
+    /// The layout of the lines, `None` if they need to be laid out anew
+    pub(crate) layout: Option<DiffLayout>,
+}
+
+impl RenderedDiff {
+    pub(crate) fn new(files: Vec<RenderedFile>) -> RenderedDiff {
+        RenderedDiff {
+            files,
+            layout: None,
+        }
+    }
+    /// Builds the lines of the files anew for the given layout, leaving out the hunks of
+    /// folded files
+    pub(crate) fn lay_out(&mut self, layout: DiffLayout, folded_paths: &HashSet<String>) {
+        for file in &mut self.files {
+            let folded = file
+                .path
+                .as_ref()
+                .is_some_and(|path| folded_paths.contains(path));
+            file.lay_out(layout, folded);
+        }
+        self.layout = Some(layout);
+    }
+    /// All the lines of the diff area
+    pub(crate) fn lines(&self) -> impl Iterator<Item = &Line<'static>> {
+        self.files.iter().flat_map(|file| &file.lines)
+    }
+    /// The index of the first line of each file
+    pub(crate) fn file_starts(&self) -> Vec<usize> {
+        self.files
+            .iter()
+            .scan(0, |start, file| {
+                let file_start = *start;
+                *start += file.lines.len();
+                Some(file_start)
+            })
+            .collect()
+    }
+    /// The index of the file that the line is in
+    pub(crate) fn file_at(&self, line: usize) -> Option<usize> {
+        let starts = self.file_starts();
+        starts.iter().rposition(|start| *start <= line)
+    }
+    /// The index of the header line of each hunk that is shown
+    pub(crate) fn hunk_starts(&self) -> Vec<usize> {
+        self.file_starts()
+            .into_iter()
+            .zip(&self.files)
+            .flat_map(|(file_start, file)| file.hunk_starts.iter().map(move |s| file_start + s))
+            .collect()
+    }
+}
+
+/// An entry of the file list, and what is shown for it in the diff area
+#[derive(Clone)]
+pub(crate) struct RenderedFile {
+    pub(crate) index_line: Line<'static>,
+    /// The path that folding is remembered for, if the entry can be folded
+    pub(crate) path: Option<String>,
+    /// Lines in front of the hunks, like the name of the file
+    pub(crate) header: Vec<Line<'static>>,
+    pub(crate) hunks: Vec<Hunk>,
+    /// Whether the hunks have an old and a new side, which isn't the case for combined
+    /// diffs and word diffs
+    pub(crate) has_sides: bool,
+    /// Whether only the header is shown
+    pub(crate) folded: bool,
+    /// The lines shown in the diff area, in the current layout
+    pub(crate) lines: Vec<Line<'static>>,
+    /// The index of the header line of each hunk within the lines
+    pub(crate) hunk_starts: Vec<usize>,
+}
+
+impl RenderedFile {
+    pub(crate) fn new(
+        index_line: Line<'static>,
+        path: Option<String>,
+        header: Vec<Line<'static>>,
+        hunks: Vec<Hunk>,
+        has_sides: bool,
+    ) -> RenderedFile {
+        RenderedFile {
+            index_line,
+            path,
+            header,
+            hunks,
+            has_sides,
+            folded: false,
+            lines: Vec::new(),
+            hunk_starts: Vec::new(),
+        }
+    }
+    fn lay_out(&mut self, layout: DiffLayout, folded: bool) {
+        let mut lines = self.header.clone();
+        let mut hunk_starts = Vec::new();
+        if folded && !self.hunks.is_empty() {
+            let hunks = self.hunks.len();
+            let s = if hunks == 1 { "" } else { "s" };
+            lines.push(Line::styled(
+                format!("{hunks} hunk{s} folded"),
+                Style::default().dark_gray(),
+            ));
+        } else {
+            let number_width = self.number_width();
+            for hunk in &self.hunks {
+                hunk_starts.push(lines.len());
+                lines.push(hunk.header.clone());
+                match layout {
+                    DiffLayout::SideBySide(width) if self.has_sides => {
+                        lines.extend(side_by_side_lines(hunk, width, number_width));
+                    }
+                    DiffLayout::Unified { line_numbers: true } => lines.extend(
+                        hunk.lines
+                            .iter()
+                            .map(|line| numbered_line(line, number_width)),
+                    ),
+                    _ => lines.extend(hunk.lines.iter().map(|line| line.line.clone())),
+                }
+            }
+        }
+        lines.push(Line::from(""));
+        self.folded = folded;
+        self.lines = lines;
+        self.hunk_starts = hunk_starts;
+    }
+    /// Digits of the largest line number in the hunks
+    fn number_width(&self) -> usize {
+        let largest = self
+            .hunks
+            .iter()
+            .flat_map(|hunk| &hunk.lines)
+            .flat_map(|line| [line.old, line.new])
+            .flatten()
+            .max()
+            .unwrap_or(0);
+        largest.to_string().len()
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Hunk {
+    /// The `@@ -a,b +c,d @@` line
+    pub(crate) header: Line<'static>,
+    pub(crate) lines: Vec<HunkLine>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum HunkLineKind {
+    Context,
+    Removed,
+    Added,
+    /// A line of a word diff, which can have both removed and added words
+    Words,
+}
+
+#[derive(Clone)]
+pub(crate) struct HunkLine {
+    pub(crate) kind: HunkLineKind,
+    /// Number of the line in the old file, if it is in there
+    pub(crate) old: Option<u32>,
+    /// Number of the line in the new file, if it is in there
+    pub(crate) new: Option<u32>,
+    /// The styled line, including the `+` and `-` markers
+    pub(crate) line: Line<'static>,
+}
+
+/// Styles a line of the diff, which has a column of markers per parent
+fn style_line(line: &str, parents: usize) -> Line<'static> {
+    let markers = line.get(..parents).unwrap_or(line);
+    let st = if line.starts_with("@@") {
+        Style::default().blue()
+    } else if markers.contains('+') {
+        Style::default().green()
+    } else if markers.contains('-') {
+        Style::default().red()
+    } else {
+        Style::default()
+    };
+    Line::from(line.to_owned()).style(st)
+}
+
+/// Splits the diff of a file into the lines in front of the first hunk, and the hunks,
+/// showing the changes within lines as requested
+pub(crate) fn parse_diff(
+    diff: &str,
+    parents: usize,
+    word_diff: WordDiff,
+    algorithm: Algorithm,
+) -> (Vec<Line<'static>>, Vec<Hunk>) {
+    // Lines within hunks start with markers, so only hunk headers start with `@@`
+    let mut lines = diff.lines().peekable();
+    let mut preamble = Vec::new();
+    while let Some(line) = lines.next_if(|line| !line.starts_with("@@")) {
+        preamble.push(style_line(line, parents));
+    }
+    let mut hunks = Vec::new();
+    while let Some(header) = lines.next() {
+        let mut body = Vec::new();
+        while let Some(line) = lines.next_if(|line| !line.starts_with("@@")) {
+            body.push(line);
+        }
+        hunks.push(parse_hunk(header, &body, parents, word_diff, algorithm));
+    }
+    (preamble, hunks)
+}
+
+fn parse_hunk(
+    header: &str,
+    body: &[&str],
+    parents: usize,
+    word_diff: WordDiff,
+    algorithm: Algorithm,
+) -> Hunk {
+    // The first line numbers, from `-a,b` and `+c,d`
+    let start = |prefix: char| {
+        header
+            .split(' ')
+            .find_map(|range| range.strip_prefix(prefix)?.split(',').next()?.parse().ok())
+            .unwrap_or(1)
+    };
+    let mut numbers = (start('-'), start('+'));
+    let mut lines = Vec::with_capacity(body.len());
+    let mut push = |kind: HunkLineKind, line: Line<'static>, advance: (u32, u32)| {
+        // Combined diffs have several old files, so only the new lines are numbered
+        let (in_old, in_new) = match kind {
+            HunkLineKind::Context => (parents == 1, true),
+            HunkLineKind::Removed => (parents == 1, false),
+            HunkLineKind::Added => (false, true),
+            HunkLineKind::Words => (false, false),
+        };
+        lines.push(HunkLine {
+            kind,
+            old: in_old.then_some(numbers.0),
+            new: in_new.then_some(numbers.1),
+            line,
+        });
+        numbers.0 += in_old as u32 + advance.0;
+        numbers.1 += in_new as u32 + advance.1;
+    };
+    let kind_of = |line: &str| {
+        let markers = line.get(..parents).unwrap_or(line);
+        if markers.contains('-') {
+            HunkLineKind::Removed
+        } else if markers.contains('+') {
+            HunkLineKind::Added
+        } else {
+            HunkLineKind::Context
+        }
+    };
+
+    // Words are only compared for diffs against a single parent
+    if parents != 1 || word_diff == WordDiff::Lines {
+        for line in body {
+            push(kind_of(line), style_line(line, parents), (0, 0));
+        }
+        return Hunk {
+            header: style_line(header, parents),
+            lines,
+        };
+    }
+    let block_end = |start: usize, marker: char| {
+        body[start..]
+            .iter()
+            .position(|line| !line.starts_with(marker))
+            .map_or(body.len(), |pos| start + pos)
+    };
+    let mut idx = 0;
+    while idx < body.len() {
+        // A block of removed lines and the added lines that follow it
+        let removed_end = block_end(idx, '-');
+        let added_end = block_end(removed_end, '+');
+        if added_end == idx {
+            let line = body[idx];
+            if word_diff == WordDiff::Words {
+                let text = line.get(1..).unwrap_or_default().to_owned();
+                push(HunkLineKind::Context, Line::from(text), (0, 0));
+            } else {
+                push(HunkLineKind::Context, style_line(line, parents), (0, 0));
+            }
+            idx += 1;
+            continue;
+        }
+        let removed = without_markers(&body[idx..removed_end]);
+        let added = without_markers(&body[removed_end..added_end]);
+        if word_diff == WordDiff::Words {
+            // The lines of the block don't correspond to old or new lines, but the ones
+            // after it do again
+            let mut advance = (removed.len() as u32, added.len() as u32);
+            for line in word_diff_block(&removed, &added, algorithm) {
+                push(HunkLineKind::Words, line, std::mem::take(&mut advance));
+            }
+        } else {
+            let kinds = std::iter::repeat_n(HunkLineKind::Removed, removed.len())
+                .chain(std::iter::repeat_n(HunkLineKind::Added, added.len()));
+            for (kind, line) in kinds.zip(highlight_block(&removed, &added, algorithm)) {
+                push(kind, line, (0, 0));
+            }
+        }
+        idx = added_end;
+    }
+    Hunk {
+        header: style_line(header, parents),
+        lines,
+    }
+}
+
+/// The line with a gutter of its old and new line numbers in front
+fn numbered_line(line: &HunkLine, number_width: usize) -> Line<'static> {
+    let number = |number: Option<u32>| number.map(|n| n.to_string()).unwrap_or_default();
+    let gutter = format!(
+        "{:>number_width$} {:>number_width$} │",
+        number(line.old),
+        number(line.new)
+    );
+    // The style of the line goes to its spans, so that the gutter doesn't get it
+    let mut spans = vec![Span::styled(gutter, Style::default().dark_gray())];
+    spans.extend(
+        line.line
+            .spans
+            .iter()
+            .map(|span| span.clone().style(line.line.style.patch(span.style))),
+    );
+    Line::from(spans)
+}
+
+fn without_markers<'a>(lines: &[&'a str]) -> Vec<&'a str> {
+    lines
+        .iter()
+        .map(|line| line.get(1..).unwrap_or_default())
+        .collect()
+}
+
+/// The rows of a hunk with the old lines on the left and the new ones on the right, with
+/// empty space on the other side of lines that were only removed or added
+fn side_by_side_lines(hunk: &Hunk, width: u16, number_width: usize) -> Vec<Line<'static>> {
+    let separator = " │ ";
+    let text_width =
+        ((width as usize).saturating_sub(separator.width()) / 2).saturating_sub(number_width + 1);
+    let row = |old: Option<&HunkLine>, new: Option<&HunkLine>| {
+        let mut spans = side_cell(old.map(|l| (l, l.old)), number_width, text_width);
+        spans.push(Span::styled(separator, Style::default().dark_gray()));
+        spans.extend(side_cell(new.map(|l| (l, l.new)), number_width, text_width));
+        Line::from(spans)
+    };
+    let mut rows = Vec::new();
+    let mut lines = hunk.lines.iter().peekable();
+    while let Some(line) = lines.peek() {
+        if line.kind == HunkLineKind::Context {
+            rows.push(row(Some(line), Some(line)));
+            lines.next();
+            continue;
+        }
+        let mut removed = Vec::new();
+        while let Some(line) = lines.next_if(|line| line.kind == HunkLineKind::Removed) {
+            removed.push(line);
+        }
+        let mut added = Vec::new();
+        while let Some(line) = lines.next_if(|line| line.kind == HunkLineKind::Added) {
+            added.push(line);
+        }
+        for idx in 0..removed.len().max(added.len()) {
+            rows.push(row(removed.get(idx).copied(), added.get(idx).copied()));
+        }
+    }
+    rows
+}
+
+/// One side of a row of a side by side diff, the line number and the text without its
+/// marker, cut off or padded to the width
+fn side_cell(
+    line: Option<(&HunkLine, Option<u32>)>,
+    number_width: usize,
+    text_width: usize,
+) -> Vec<Span<'static>> {
+    let Some((line, number)) = line else {
+        return vec![Span::raw(" ".repeat(number_width + 1 + text_width))];
+    };
+    let number = number.map(|n| n.to_string()).unwrap_or_default();
+    let mut spans = vec![Span::styled(
+        format!("{number:>number_width$} "),
+        Style::default().dark_gray(),
+    )];
+    let mut column = 0;
+    'spans: for (idx, span) in line.line.spans.iter().enumerate() {
+        let mut content = span.content.chars();
+        if idx == 0 {
+            // The marker, which the side already tells
+            content.next();
+        }
+        let mut text = String::new();
+        for c in content {
+            let c_width = match c {
+                '\t' => TAB_WIDTH - column % TAB_WIDTH,
+                c => c.width().unwrap_or(0),
+            };
+            if column + c_width > text_width {
+                spans.push(Span::styled(text, line.line.style.patch(span.style)));
+                break 'spans;
+            }
+            if c == '\t' {
+                text.extend(std::iter::repeat_n(' ', c_width));
+            } else {
+                text.push(c);
+            }
+            column += c_width;
+        }
+        spans.push(Span::styled(text, line.line.style.patch(span.style)));
+    }
+    spans.push(Span::raw(" ".repeat(text_width - column)));
+    spans
+}
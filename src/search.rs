@@ -0,0 +1,397 @@
+use ratatui::{style::Style, text::Span};
+
+use regex::Regex;
+
+use crate::{
+    State,
+    model::{CommitShallow, ShallowId},
+    pickaxe::{Pickaxe, PickaxeKind},
+};
+
+/// How many more commits to load from the walk per step of a search
+const SEARCH_CHUNK: usize = 500;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SearchDirection {
+    Forward,
+    Backward,
+}
+
+pub(crate) enum PromptKind {
+    MessageSearch,
+    PickaxeOccurrences,
+    PickaxeLinesMatching,
+    DiffSearch,
+    FileFilter,
+}
+
+/// A line of text input at the bottom of the screen
+pub(crate) struct Prompt {
+    pub(crate) kind: PromptKind,
+    pub(crate) input: String,
+}
+
+impl Prompt {
+    pub(crate) fn prefix(&self) -> &'static str {
+        match self.kind {
+            PromptKind::MessageSearch => "/",
+            PromptKind::PickaxeOccurrences => "-S ",
+            PromptKind::PickaxeLinesMatching => "-G ",
+            PromptKind::DiffSearch => "diff/",
+            PromptKind::FileFilter => "files: ",
+        }
+    }
+}
+
+pub(crate) enum SearchKind {
+    /// Through the titles, bodies and authors of commits, ignoring case
+    Message {
+        /// The query in lowercase
+        needle: String,
+        /// The selection that a forward search went from, and the length of the log it had
+        /// looked through without a match, so that the next step continues from there
+        searched: Option<(usize, usize)>,
+    },
+    /// Through the diffs of commits
+    Pickaxe(Pickaxe),
+}
+
+pub(crate) struct Search {
+    /// The needle as the user entered it
+    pub(crate) query: String,
+    pub(crate) kind: SearchKind,
+    /// Set while the log needs to be extended to find the next match
+    pub(crate) pending: bool,
+}
+
+impl Search {
+    pub(crate) fn matches(&self, cmt: &CommitShallow) -> bool {
+        match &self.kind {
+            SearchKind::Message { needle, .. } => cmt.matches(needle),
+            SearchKind::Pickaxe(pickaxe) => match cmt.id {
+                ShallowId::CommitId(id, _prefix) => pickaxe.matches.contains(&id),
+                ShallowId::Worktree | ShallowId::Index => false,
+            },
+        }
+    }
+    /// The needle to highlight in the texts of the log, if the search goes through them
+    pub(crate) fn text_needle(&self) -> Option<&str> {
+        match &self.kind {
+            SearchKind::Message { needle, .. } => Some(needle),
+            SearchKind::Pickaxe(_) => None,
+        }
+    }
+    /// A line about the outcome of the search, once it is known
+    fn summary(&self) -> Option<String> {
+        match &self.kind {
+            SearchKind::Message { .. } => None,
+            SearchKind::Pickaxe(pickaxe) => pickaxe.done.then(|| {
+                format!(
+                    "Found {} commits changing '{}' in {} commits",
+                    pickaxe.matches.len(),
+                    self.query,
+                    pickaxe.searched
+                )
+            }),
+        }
+    }
+    /// Whether more matches might still come in from the background
+    fn awaiting_matches(&self) -> bool {
+        match &self.kind {
+            SearchKind::Message { .. } => false,
+            SearchKind::Pickaxe(pickaxe) => !pickaxe.done,
+        }
+    }
+}
+
+impl CommitShallow {
+    pub(crate) fn matches(&self, needle: &str) -> bool {
+        [
+            &self.commit,
+            &self.body,
+            &self.signature.author_name,
+            &self.signature.author_email,
+        ]
+        .iter()
+        .any(|s| s.to_lowercase().contains(needle))
+    }
+}
+
+/// Splits the text into spans, with every occurrence of the lowercase needle highlighted
+pub(crate) fn highlight_spans(text: &str, needle: &str, style: Style) -> Vec<Span<'static>> {
+    // Lowercasing can change the byte lengths of characters, so remember for every byte of
+    // the lowercase text which character of the text it came from
+    let mut lower = String::with_capacity(text.len());
+    let mut origins = Vec::with_capacity(text.len());
+    for (start, c) in text.char_indices() {
+        let origin = start..start + c.len_utf8();
+        for lower_c in c.to_lowercase() {
+            lower.push(lower_c);
+            origins.resize(lower.len(), origin.clone());
+        }
+    }
+    if needle.is_empty() || !lower.contains(needle) {
+        return vec![Span::styled(text.to_owned(), style)];
+    }
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    for (lower_start, _) in lower.match_indices(needle) {
+        let start = origins[lower_start].start;
+        let end = origins[lower_start + needle.len() - 1].end;
+        if start < pos {
+            continue;
+        }
+        spans.push(Span::styled(text[pos..start].to_owned(), style));
+        spans.push(Span::styled(
+            text[start..end].to_owned(),
+            style.patch(match_style()),
+        ));
+        pos = end;
+    }
+    spans.push(Span::styled(text[pos..].to_owned(), style));
+    spans
+}
+
+pub(crate) fn match_style() -> Style {
+    Style::default()
+        .fg(ratatui::style::Color::Black)
+        .bg(ratatui::style::Color::Yellow)
+}
+
+impl State {
+    pub(crate) fn start_message_search(&mut self, query: String) {
+        if query.is_empty() {
+            self.search = None;
+            return;
+        }
+        self.search = Some(Search {
+            kind: SearchKind::Message {
+                needle: query.to_lowercase(),
+                searched: None,
+            },
+            query,
+            pending: false,
+        });
+        self.find_match(SearchDirection::Forward);
+    }
+    pub(crate) fn start_pickaxe_search(&mut self, prompt_kind: PromptKind, query: String) {
+        self.search = None;
+        if query.is_empty() {
+            return;
+        }
+        let kind = match prompt_kind {
+            PromptKind::PickaxeLinesMatching => match Regex::new(&query) {
+                Ok(regex) => PickaxeKind::LinesMatching(regex),
+                Err(e) => {
+                    // The full message spans several lines, pointing at the error position
+                    let msg = e.to_string();
+                    let msg = msg.lines().last().unwrap_or_default();
+                    self.status = Some(format!("invalid regex: {msg}"));
+                    return;
+                }
+            },
+            _ => PickaxeKind::Occurrences(query.clone()),
+        };
+        let revisions = match self.resolve_revisions() {
+            Ok(revisions) => revisions,
+            Err(e) => {
+                self.status = Some(format!("error: {e}"));
+                return;
+            }
+        };
+        let pickaxe = Pickaxe::spawn(
+            self.repo.clone(),
+            self.commit_order,
+            revisions,
+            self.path_limit(),
+            kind,
+        );
+        self.search = Some(Search {
+            query,
+            kind: SearchKind::Pickaxe(pickaxe),
+            pending: false,
+        });
+        self.find_match(SearchDirection::Forward);
+    }
+    /// Selects the next match in the given direction among the loaded commits, or starts
+    /// extending the log to look for one further down
+    pub(crate) fn find_match(&mut self, direction: SearchDirection) {
+        let Some(mut search) = self.search.take() else {
+            return;
+        };
+        if self.commits_shallow_loading() {
+            // Look again once the log has arrived
+            self.search = Some(Search {
+                pending: true,
+                ..search
+            });
+            return;
+        }
+        let selection_idx = self.selection_idx;
+        let walk_exhausted = self.commits_walk_exhausted;
+        let commits = &self.commits_shallow_cached;
+        let forward_start = match &search.kind {
+            SearchKind::Message {
+                searched: Some((from_idx, searched_len)),
+                ..
+            } if *from_idx == selection_idx => *searched_len,
+            _ => selection_idx + 1,
+        };
+        let found = match direction {
+            SearchDirection::Forward => commits
+                .iter()
+                .enumerate()
+                .skip(forward_start)
+                .find(|(_idx, cmt)| search.matches(cmt)),
+            SearchDirection::Backward => commits
+                .iter()
+                .enumerate()
+                .take(selection_idx)
+                .rev()
+                .find(|(_idx, cmt)| search.matches(cmt)),
+        }
+        .map(|(idx, _cmt)| idx);
+        let commits_len = commits.len();
+
+        let query = &search.query;
+        let mut pending = false;
+        if let Some(idx) = found {
+            self.select_commit(idx);
+            self.status = search.summary();
+        } else if direction == SearchDirection::Forward && !walk_exhausted {
+            pending = match &search.kind {
+                SearchKind::Message { .. } => {
+                    self.status = Some(format!("Searching for '{query}'… {commits_len} commits"));
+                    true
+                }
+                // The pickaxe reports its own progress
+                SearchKind::Pickaxe(pickaxe) => {
+                    let loaded_matches = commits.iter().filter(|cmt| search.matches(cmt)).count();
+                    pickaxe.matches.len() > loaded_matches || !pickaxe.done
+                }
+            };
+        } else if direction == SearchDirection::Forward && search.awaiting_matches() {
+            pending = true;
+        }
+        if !pending && found.is_none() {
+            self.status = Some(format!("No more matches for '{query}'"));
+        }
+        if let SearchKind::Message { searched, .. } = &mut search.kind {
+            *searched = (direction == SearchDirection::Forward && found.is_none())
+                .then_some((selection_idx, commits_len));
+        }
+        self.search = Some(Search { pending, ..search });
+    }
+    /// Continues a search that needs more commits to be loaded, or more results from the
+    /// background thread.
+    ///
+    /// This is done in steps from the event loop, so that the search can be cancelled.
+    pub(crate) fn continue_search(&mut self) {
+        if let Some(search) = &mut self.search
+            && let SearchKind::Pickaxe(pickaxe) = &mut search.kind
+        {
+            let was_done = pickaxe.done;
+            if let Err(e) = pickaxe.poll() {
+                let query = &search.query;
+                self.status = Some(format!("error while searching for '{query}': {e}"));
+            } else if !was_done && pickaxe.done && !search.pending && self.status.is_none() {
+                self.status = search.summary();
+            }
+        }
+        if !self.search_pending() || self.commits_shallow_loading() {
+            return;
+        }
+        self.find_match(SearchDirection::Forward);
+        if self.search_needs_commits() {
+            self.wanted_commit_list_count += SEARCH_CHUNK;
+        }
+    }
+    /// Whether the pending search needs more commits to be loaded, as opposed to waiting
+    /// for results from the background
+    pub(crate) fn search_needs_commits(&self) -> bool {
+        let Some(search) = self.search.as_ref().filter(|s| s.pending) else {
+            return false;
+        };
+        if self.commits_shallow_loading() {
+            return false;
+        }
+        match &search.kind {
+            SearchKind::Message { .. } => true,
+            SearchKind::Pickaxe(pickaxe) => {
+                let commits = &self.commits_shallow_cached;
+                let loaded_matches = commits.iter().filter(|cmt| search.matches(cmt)).count();
+                pickaxe.matches.len() > loaded_matches
+            }
+        }
+    }
+    pub(crate) fn cancel_search(&mut self) {
+        if let Some(search) = &mut self.search {
+            search.pending = false;
+            if let SearchKind::Pickaxe(pickaxe) = &mut search.kind {
+                pickaxe.cancel();
+            }
+        }
+        self.status = Some("Search cancelled".to_owned());
+    }
+    pub(crate) fn search_pending(&self) -> bool {
+        self.search.as_ref().is_some_and(|s| s.pending)
+    }
+    /// Whether a search is still running in the background
+    pub(crate) fn search_running(&self) -> bool {
+        self.search.as_ref().is_some_and(|s| s.awaiting_matches())
+    }
+    /// The progress of a search running in the background, to show in the status line
+    pub(crate) fn search_progress(&self) -> Option<String> {
+        let search = self.search.as_ref()?;
+        let SearchKind::Pickaxe(pickaxe) = &search.kind else {
+            return None;
+        };
+        (!pickaxe.done).then(|| {
+            format!(
+                "Searching diffs for '{}'… {} commits searched, {} matches",
+                search.query,
+                pickaxe.searched,
+                pickaxe.matches.len()
+            )
+        })
+    }
+    /// Selects the commit at the given index of the log, scrolling it into view
+    pub(crate) fn select_commit(&mut self, idx: usize) {
+        let log_h = self.last_log_area.height.saturating_sub(2) as usize;
+        self.selection_idx = idx;
+        if idx < self.commits_scroll_idx || idx >= self.commits_scroll_idx + log_h {
+            self.commits_scroll_idx = idx.saturating_sub(log_h / 2);
+        }
+        self.diff_scroll_idx = 0;
+        self.file_cursor = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn highlighted(text: &str, needle: &str) -> Vec<String> {
+        highlight_spans(text, needle, Style::default())
+            .into_iter()
+            .filter(|span| span.style == match_style())
+            .map(|span| span.content.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn matches_are_highlighted_ignoring_case() {
+        assert_eq!(highlighted("Fix foo, then FOO", "foo"), ["foo", "FOO"]);
+        assert!(highlighted("Fix foo", "bar").is_empty());
+    }
+
+    #[test]
+    fn lowercasing_that_changes_lengths_keeps_char_boundaries() {
+        // `ẞ` gets shorter and `İ` longer when lowercased, keeping the total length
+        assert_eq!(highlighted("ẞİ x", "x"), ["x"]);
+        assert_eq!(highlighted("aẞİb", "i\u{307}b"), ["İb"]);
+        assert_eq!(highlighted("ẞİ", "ß"), ["ẞ"]);
+        // Part of what a character lowercases to highlights the whole character
+        assert_eq!(highlighted("İx", "i"), ["İ"]);
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::Path;
+
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::Theme,
+    parsing::{SyntaxReference, SyntaxSet},
+};
+use two_face::theme::EmbeddedThemeName;
+
+use crate::rendered_diff::{Hunk, HunkLineKind};
+
+/// Lines of a diff to highlight at most across all of its files, as it would take too long
+pub(crate) const MAX_HIGHLIGHTED_LINES: usize = 5000;
+
+/// Backgrounds of removed and added lines, as the foreground is taken by the syntax
+const REMOVED_BG: Color = Color::Indexed(52);
+const ADDED_BG: Color = Color::Indexed(22);
+/// Backgrounds of the changed words within removed and added lines
+const REMOVED_WORD_BG: Color = Color::Indexed(88);
+const ADDED_WORD_BG: Color = Color::Indexed(28);
+
+/// The bundled grammars and theme for syntax highlighting
+pub(crate) struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+impl Highlighter {
+    pub(crate) fn new() -> Highlighter {
+        let theme = two_face::theme::extra()
+            .get(EmbeddedThemeName::MonokaiExtended)
+            .clone();
+        Highlighter {
+            syntaxes: two_face::syntax::extra_newlines(),
+            theme,
+        }
+    }
+    /// The grammar for the file, from its name or its extension
+    fn syntax(&self, path: &str) -> Option<&SyntaxReference> {
+        let path = Path::new(path);
+        let file_name = path.file_name()?.to_str()?;
+        self.syntaxes
+            .find_syntax_by_extension(file_name)
+            .or_else(|| {
+                let extension = path.extension()?.to_str()?;
+                self.syntaxes.find_syntax_by_extension(extension)
+            })
+    }
+    /// Colors the text of the lines in the hunks of the file, keeping the markers and the
+    /// backgrounds of changes.
+    ///
+    /// The old and the new lines are highlighted separately, each as if the hunks were all
+    /// there is of the file.
+    ///
+    /// The lines are taken from the budget, files with more lines than are left in it are
+    /// not highlighted.
+    pub(crate) fn highlight_hunks(&self, path: &str, hunks: &mut [Hunk], budget: &mut usize) {
+        let lines = hunks.iter().map(|hunk| hunk.lines.len()).sum::<usize>();
+        if lines > *budget {
+            return;
+        }
+        let Some(syntax) = self.syntax(path) else {
+            return;
+        };
+        *budget -= lines;
+        let mut old = HighlightLines::new(syntax, &self.theme);
+        let mut new = HighlightLines::new(syntax, &self.theme);
+        for line in hunks.iter_mut().flat_map(|hunk| &mut hunk.lines) {
+            let (in_old, in_new, bg, word_bg) = match line.kind {
+                HunkLineKind::Context => (true, true, None, None),
+                HunkLineKind::Removed => (true, false, Some(REMOVED_BG), Some(REMOVED_WORD_BG)),
+                HunkLineKind::Added => (false, true, Some(ADDED_BG), Some(ADDED_WORD_BG)),
+                // Word diffs keep their colors
+                HunkLineKind::Words => continue,
+            };
+            let Some((marker, text_spans)) = line.line.spans.split_first() else {
+                continue;
+            };
+            // The marker is the first character, which can share the span with text
+            let marker_len = marker.content.chars().next().map_or(0, char::len_utf8);
+            let mut diff_spans = vec![(marker.style, &marker.content[marker_len..])];
+            diff_spans.extend(text_spans.iter().map(|s| (s.style, s.content.as_ref())));
+            let text = diff_spans.iter().map(|(_style, s)| *s).collect::<String>() + "\n";
+
+            // Context lines are in both files, so both need to see them
+            let old_highlighted = in_old.then(|| old.highlight_line(&text, &self.syntaxes));
+            let new_highlighted = in_new.then(|| new.highlight_line(&text, &self.syntaxes));
+            let Some(Ok(highlighted)) = new_highlighted.or(old_highlighted) else {
+                continue;
+            };
+
+            let mut spans = vec![Span::styled(
+                marker.content[..marker_len].to_owned(),
+                line.line.style.patch(marker.style),
+            )];
+            let mut diff_spans = diff_spans.into_iter().filter(|(_style, s)| !s.is_empty());
+            let mut diff_span = diff_spans.next();
+            for (syntax_style, mut piece) in highlighted {
+                let fg = syntax_style.foreground;
+                let fg = Color::Rgb(fg.r, fg.g, fg.b);
+                // Cut the piece where the styles of the diff change
+                while let Some((diff_style, diff_text)) = &mut diff_span {
+                    if piece.is_empty() {
+                        break;
+                    }
+                    let len = piece.len().min(diff_text.len());
+                    let mut style = Style::default().fg(fg);
+                    if let Some(word_bg) = word_bg
+                        && diff_style.add_modifier.contains(Modifier::REVERSED)
+                    {
+                        style = style.bg(word_bg);
+                    }
+                    spans.push(Span::styled(piece[..len].to_owned(), style));
+                    piece = &piece[len..];
+                    *diff_text = &diff_text[len..];
+                    if diff_text.is_empty() {
+                        diff_span = diff_spans.next();
+                    }
+                }
+            }
+            let mut line_style = Style::default();
+            if let Some(bg) = bg {
+                line_style = line_style.bg(bg);
+            }
+            line.line = Line::from(spans).style(line_style);
+        }
+    }
+}
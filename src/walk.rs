@@ -0,0 +1,477 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use gix::{ObjectId, Repository, bstr::ByteSlice};
+
+/// The order in which commits are listed in the log area
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum CommitOrder {
+    /// Like `git log --date-order`
+    #[default]
+    CommitterDate,
+    /// Like `git log --author-date-order`
+    AuthorDate,
+    /// Like `git log --topo-order`
+    Topo,
+}
+
+impl CommitOrder {
+    /// The order after this one, for cycling through them at runtime
+    pub(crate) fn next(self) -> CommitOrder {
+        match self {
+            CommitOrder::CommitterDate => CommitOrder::AuthorDate,
+            CommitOrder::AuthorDate => CommitOrder::Topo,
+            CommitOrder::Topo => CommitOrder::CommitterDate,
+        }
+    }
+}
+
+impl std::fmt::Display for CommitOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            CommitOrder::CommitterDate => "date order",
+            CommitOrder::AuthorDate => "author date order",
+            CommitOrder::Topo => "topo order",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Limits the walk to commits that change the given paths, like `git log -- <paths>`
+#[derive(Clone)]
+pub(crate) struct PathLimit {
+    pub(crate) paths: Vec<PathBuf>,
+    /// Follow renames of a single file, like `git log --follow`
+    pub(crate) follow: bool,
+}
+
+/// A commit yielded by the walk
+pub(crate) struct WalkedCommit {
+    pub(crate) id: ObjectId,
+    /// The parents after history simplification, i.e. only the ones that the walk follows
+    pub(crate) parents: Vec<ObjectId>,
+    /// Whether the commit should be shown. If not, it has at most one parent, which stands
+    /// in for the commit in the graph.
+    pub(crate) shown: bool,
+}
+
+struct Node {
+    commit_time: i64,
+    author_time: i64,
+    /// Position in discovery order, used as a tie breaker
+    seq: usize,
+    tree_id: ObjectId,
+    parents: Vec<ObjectId>,
+    /// The paths to limit to in this commit, which differ between commits when following renames
+    paths: Option<Arc<[PathBuf]>>,
+    shown: bool,
+    /// Number of explored children that have not been yielded yet
+    pending_children: usize,
+    explored: bool,
+    /// Whether the commit is reachable from an excluded commit, like `a` in `a..b`
+    hidden: bool,
+    yielded: bool,
+}
+
+/// A lazy history walk that never yields a commit before its children.
+///
+/// Commits are explored newest first by the date of the order (the committer date unless
+/// ordered by author date), ahead of what is yielded. A commit is only yielded once no
+/// unexplored commit is newer than it, as those might still turn out to be its children.
+/// This is the same approach git takes with generation numbers, but with commit dates
+/// instead, so it stays lazy even without a commit-graph file.
+///
+/// Commits reachable from the hidden commits are not yielded, and the walk ends once only
+/// those are left.
+///
+/// If limited to paths, the history is simplified the way `git log -- <paths>` does it: a merge
+/// that has the same content at the paths as one of its parents is only followed through that
+/// parent, and commits that don't change the paths are yielded as not shown.
+///
+/// The walk is deterministic, so two walks from the same tips yield the same prefix.
+pub(crate) struct CommitWalk {
+    order: CommitOrder,
+    path_limit: Option<PathLimit>,
+    nodes: HashMap<ObjectId, Node>,
+    /// Number of commits that still need to be yielded, as far as we know
+    interesting_pending: usize,
+    unexplored: BinaryHeap<(i64, Reverse<usize>, ObjectId)>,
+    /// Commits ready to be yielded, for the date based orders
+    ready_heap: BinaryHeap<(i64, Reverse<usize>, ObjectId)>,
+    /// Commits ready to be yielded, for the topological order
+    ready_stack: Vec<ObjectId>,
+}
+
+impl CommitWalk {
+    pub(crate) fn new(
+        repo: &Repository,
+        order: CommitOrder,
+        tips: impl IntoIterator<Item = ObjectId>,
+        hidden: Vec<ObjectId>,
+        path_limit: Option<PathLimit>,
+    ) -> Result<CommitWalk, anyhow::Error> {
+        let paths = path_limit
+            .as_ref()
+            .map(|path_limit| Arc::from(path_limit.paths.as_slice()));
+        let mut walk = CommitWalk {
+            order,
+            path_limit,
+            nodes: HashMap::new(),
+            interesting_pending: 0,
+            unexplored: BinaryHeap::new(),
+            ready_heap: BinaryHeap::new(),
+            ready_stack: Vec::new(),
+        };
+        for id in tips.into_iter().chain(hidden.iter().copied()) {
+            walk.discover(repo, id)?;
+            walk.nodes.get_mut(&id).unwrap().paths = paths.clone();
+        }
+        for id in hidden {
+            walk.mark_hidden(id);
+        }
+        Ok(walk)
+    }
+    fn discover(&mut self, repo: &Repository, id: ObjectId) -> Result<(), anyhow::Error> {
+        if self.nodes.contains_key(&id) {
+            return Ok(());
+        }
+        let commit = repo.find_commit(id)?;
+        let node = Node {
+            commit_time: commit.time()?.seconds,
+            author_time: commit.author()?.time()?.seconds,
+            seq: self.nodes.len(),
+            tree_id: commit.tree_id()?.detach(),
+            parents: commit.parent_ids().map(|id| id.detach()).collect(),
+            paths: None,
+            shown: true,
+            pending_children: 0,
+            explored: false,
+            hidden: false,
+            yielded: false,
+        };
+        self.interesting_pending += 1;
+        self.unexplored
+            .push((self.order_time(&node), Reverse(node.seq), id));
+        self.nodes.insert(id, node);
+        Ok(())
+    }
+    /// Hides the commit and all of its ancestors that have been discovered so far
+    fn mark_hidden(&mut self, id: ObjectId) {
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let node = self.nodes.get_mut(&id).unwrap();
+            if node.hidden {
+                continue;
+            }
+            node.hidden = true;
+            if !node.yielded {
+                self.interesting_pending -= 1;
+            }
+            // Parents of unexplored commits get hidden once they are explored
+            if node.explored {
+                stack.extend(node.parents.iter().copied());
+            }
+        }
+    }
+    fn explore(&mut self, repo: &Repository, id: ObjectId) -> Result<(), anyhow::Error> {
+        let hidden = self.nodes[&id].hidden;
+        // Hidden commits keep all of their parents so that everything reachable gets hidden
+        if self.path_limit.is_some() && !hidden {
+            self.simplify(repo, id)?;
+        } else {
+            for parent_id in self.nodes[&id].parents.clone() {
+                self.discover(repo, parent_id)?;
+            }
+        }
+        for parent_id in self.nodes[&id].parents.clone() {
+            self.nodes.get_mut(&parent_id).unwrap().pending_children += 1;
+            if hidden {
+                self.mark_hidden(parent_id);
+            }
+        }
+        let node = self.nodes.get_mut(&id).unwrap();
+        node.explored = true;
+        if node.pending_children == 0 {
+            self.push_ready(id);
+        }
+        Ok(())
+    }
+    /// Decides whether to show the commit and which of its parents to follow, based on
+    /// whether it changes the paths we are limited to, and discovers the followed parents.
+    ///
+    /// Parents that are not followed are not discovered, so that they are only walked if
+    /// they are reachable in another way.
+    fn simplify(&mut self, repo: &Repository, id: ObjectId) -> Result<(), anyhow::Error> {
+        let Some(path_limit) = &self.path_limit else {
+            return Ok(());
+        };
+        let follow = path_limit.follow;
+        let node = &self.nodes[&id];
+        // A commit is normally explored after a child that passed on the paths, unless a
+        // skewed commit date puts it first
+        let paths = match &node.paths {
+            Some(paths) => paths.clone(),
+            None => Arc::from(path_limit.paths.as_slice()),
+        };
+        let tree = repo.find_tree(node.tree_id)?;
+        let entries = lookup_paths(&tree, &paths)?;
+
+        let mut treesame_parent = None;
+        let mut parents = Vec::new();
+        for parent_id in node.parents.clone() {
+            let parent_tree_id = match self.nodes.get(&parent_id) {
+                Some(parent) => parent.tree_id,
+                None => repo.find_commit(parent_id)?.tree_id()?.detach(),
+            };
+            let parent_tree = repo.find_tree(parent_tree_id)?;
+            let parent_entries = lookup_paths(&parent_tree, &paths)?;
+            let treesame = entries == parent_entries;
+            if treesame && treesame_parent.is_none() {
+                treesame_parent = Some(parent_id);
+            }
+            let renamed = follow
+                && entries.first().is_some_and(Option::is_some)
+                && parent_entries.first().is_some_and(Option::is_none);
+            let rename_source = match paths.first() {
+                Some(path) if renamed => find_rename_source(repo, &parent_tree, &tree, path)?,
+                _ => None,
+            };
+            let parent_paths = match rename_source {
+                Some(source) => Arc::from([source]),
+                None => paths.clone(),
+            };
+            parents.push((parent_id, parent_paths));
+        }
+
+        let node = self.nodes.get_mut(&id).unwrap();
+        if let Some(treesame_parent) = treesame_parent {
+            parents.retain(|(parent_id, _paths)| *parent_id == treesame_parent);
+            node.parents = vec![treesame_parent];
+            node.shown = false;
+        } else if node.parents.is_empty() {
+            node.shown = entries.iter().any(Option::is_some);
+        }
+        for (parent_id, parent_paths) in parents {
+            self.discover(repo, parent_id)?;
+            let parent = self.nodes.get_mut(&parent_id).unwrap();
+            parent.paths.get_or_insert(parent_paths);
+        }
+        Ok(())
+    }
+    /// The date that the walk explores by, and that the date based orders sort by
+    fn order_time(&self, node: &Node) -> i64 {
+        match self.order {
+            CommitOrder::AuthorDate => node.author_time,
+            CommitOrder::CommitterDate | CommitOrder::Topo => node.commit_time,
+        }
+    }
+    fn push_ready(&mut self, id: ObjectId) {
+        let node = &self.nodes[&id];
+        match self.order {
+            CommitOrder::CommitterDate | CommitOrder::AuthorDate => {
+                self.ready_heap
+                    .push((self.order_time(node), Reverse(node.seq), id));
+            }
+            CommitOrder::Topo => self.ready_stack.push(id),
+        }
+    }
+    fn peek_ready(&self) -> Option<ObjectId> {
+        match self.order {
+            CommitOrder::CommitterDate | CommitOrder::AuthorDate => {
+                self.ready_heap.peek().map(|(_, _, id)| *id)
+            }
+            CommitOrder::Topo => self.ready_stack.last().copied(),
+        }
+    }
+    fn pop_ready(&mut self) -> Option<ObjectId> {
+        match self.order {
+            CommitOrder::CommitterDate | CommitOrder::AuthorDate => {
+                self.ready_heap.pop().map(|(_, _, id)| id)
+            }
+            CommitOrder::Topo => self.ready_stack.pop(),
+        }
+    }
+    /// Yield the next commit, or `None` if the history is exhausted
+    pub(crate) fn next(
+        &mut self,
+        repo: &Repository,
+    ) -> Result<Option<WalkedCommit>, anyhow::Error> {
+        loop {
+            if self.interesting_pending == 0 {
+                return Ok(None);
+            }
+            let ready_time = self
+                .peek_ready()
+                .map(|id| self.order_time(&self.nodes[&id]));
+            let unexplored_time = self.unexplored.peek().map(|(time, _, _)| *time);
+            let explore_first = match (unexplored_time, ready_time) {
+                (Some(unexplored_time), Some(ready_time)) => unexplored_time >= ready_time,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => return Ok(None),
+            };
+            if explore_first {
+                let (_, _, id) = self.unexplored.pop().unwrap();
+                self.explore(repo, id)?;
+                continue;
+            }
+            let id = self.pop_ready().unwrap();
+            let parents = self.nodes[&id].parents.clone();
+            // Like git, the topological order continues with the last parent that became ready
+            for parent_id in parents {
+                // Explore up to the parent so that it can become ready right away
+                while !self.nodes[&parent_id].explored {
+                    let (_, _, id) = self.unexplored.pop().unwrap();
+                    self.explore(repo, id)?;
+                }
+                let parent = self.nodes.get_mut(&parent_id).unwrap();
+                parent.pending_children -= 1;
+                if parent.pending_children == 0 && parent.explored {
+                    self.push_ready(parent_id);
+                }
+            }
+            let node = self.nodes.get_mut(&id).unwrap();
+            if node.hidden {
+                continue;
+            }
+            node.yielded = true;
+            self.interesting_pending -= 1;
+            return Ok(Some(WalkedCommit {
+                id,
+                parents: node.parents.clone(),
+                shown: node.shown,
+            }));
+        }
+    }
+}
+
+fn lookup_paths(
+    tree: &gix::Tree<'_>,
+    paths: &[PathBuf],
+) -> Result<Vec<Option<ObjectId>>, anyhow::Error> {
+    paths
+        .iter()
+        .map(|path| {
+            Ok(tree
+                .lookup_entry_by_path(path)?
+                .map(|entry| entry.object_id()))
+        })
+        .collect()
+}
+
+/// Finds the path that `path` in `tree` has been renamed from in `parent_tree`, if any
+fn find_rename_source(
+    repo: &Repository,
+    parent_tree: &gix::Tree<'_>,
+    tree: &gix::Tree<'_>,
+    path: &std::path::Path,
+) -> Result<Option<PathBuf>, anyhow::Error> {
+    let changes = repo.diff_tree_to_tree(parent_tree, tree, None)?;
+    for change in changes {
+        if let gix::diff::tree_with_rewrites::Change::Rewrite {
+            source_location,
+            location,
+            ..
+        } = change
+            && gix::path::from_bstr(location.as_bstr()) == path
+        {
+            return Ok(Some(
+                gix::path::from_bstr(source_location.as_bstr()).into_owned(),
+            ));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use gix::objs::tree::{Entry, EntryKind};
+
+    use super::*;
+
+    /// A bare repository in a temporary directory, removed when dropped
+    struct TestRepo {
+        dir: PathBuf,
+        repo: Repository,
+    }
+
+    impl TestRepo {
+        fn new(name: &str) -> TestRepo {
+            let dir = std::env::temp_dir().join(format!("giv-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            let repo = gix::init_bare(&dir).unwrap();
+            TestRepo { dir, repo }
+        }
+        /// Writes a commit with a tree of the given files, committed at the given time
+        fn commit(&self, files: &[(&str, &str)], parents: &[ObjectId], time: i64) -> ObjectId {
+            let entries = files
+                .iter()
+                .map(|(path, content)| Entry {
+                    mode: EntryKind::Blob.into(),
+                    filename: (*path).into(),
+                    oid: self.repo.write_blob(content).unwrap().detach(),
+                })
+                .collect();
+            let tree = self.repo.write_object(gix::objs::Tree { entries }).unwrap();
+            let signature = gix::actor::Signature {
+                name: "t".into(),
+                email: "t@t".into(),
+                time: gix::date::Time::new(time, 0),
+            };
+            let commit = gix::objs::Commit {
+                tree: tree.detach(),
+                parents: parents.iter().copied().collect(),
+                author: signature.clone(),
+                committer: signature,
+                encoding: None,
+                message: format!("commit at {time}").into(),
+                extra_headers: Vec::new(),
+            };
+            self.repo.write_object(commit).unwrap().detach()
+        }
+    }
+
+    impl Drop for TestRepo {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn walk_all(repo: &Repository, tip: ObjectId, paths: &[&str]) -> Vec<WalkedCommit> {
+        let path_limit = PathLimit {
+            paths: paths.iter().map(PathBuf::from).collect(),
+            follow: false,
+        };
+        let mut walk = CommitWalk::new(
+            repo,
+            CommitOrder::default(),
+            [tip],
+            Vec::new(),
+            Some(path_limit),
+        )
+        .unwrap();
+        std::iter::from_fn(|| walk.next(repo).unwrap()).collect()
+    }
+
+    #[test]
+    fn merge_taking_one_side_prunes_the_other() {
+        let test = TestRepo::new("walk-ours-merge");
+        let base = test.commit(&[("Y", "0")], &[], 100);
+        let main = test.commit(&[("X", "1"), ("Y", "0")], &[base], 200);
+        let side = test.commit(&[("X", "2"), ("Y", "0")], &[base], 300);
+        // Like `git merge -s ours side`, the merge has the tree of main
+        let merge = test.commit(&[("X", "1"), ("Y", "0")], &[main, side], 400);
+
+        let walked = walk_all(&test.repo, merge, &["X"]);
+        let shown = walked
+            .iter()
+            .filter(|walked| walked.shown)
+            .map(|walked| walked.id)
+            .collect::<Vec<_>>();
+        assert_eq!(shown, [main]);
+        assert!(walked.iter().all(|walked| walked.id != side));
+        assert_eq!(walked[0].parents, [main]);
+    }
+}
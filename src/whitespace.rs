@@ -0,0 +1,162 @@
+use std::ops::Range;
+
+use gix::diff::blob::{
+    intern::{InternedInput, Interner},
+    sources::byte_lines_with_terminator,
+};
+
+use crate::model::DiffOptions;
+
+/// Which differences in whitespace within lines to ignore, from least to most
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub(crate) enum IgnoreSpace {
+    #[default]
+    None,
+    /// Like git's `--ignore-cr-at-eol`
+    CrAtEol,
+    /// Like git's `-b`, changes in the amount of whitespace
+    Change,
+    /// Like git's `-w`, all whitespace
+    All,
+}
+
+impl IgnoreSpace {
+    pub(crate) fn next(self) -> IgnoreSpace {
+        match self {
+            IgnoreSpace::None => IgnoreSpace::CrAtEol,
+            IgnoreSpace::CrAtEol => IgnoreSpace::Change,
+            IgnoreSpace::Change => IgnoreSpace::All,
+            IgnoreSpace::All => IgnoreSpace::None,
+        }
+    }
+    /// The line in a form where the ignored whitespace doesn't matter any more
+    fn normalize(self, line: &[u8]) -> Vec<u8> {
+        match self {
+            IgnoreSpace::None => line.to_vec(),
+            IgnoreSpace::CrAtEol => {
+                let content = line.strip_suffix(b"\n").unwrap_or(line);
+                let content = content.strip_suffix(b"\r").unwrap_or(content);
+                let mut normalized = content.to_vec();
+                if line.ends_with(b"\n") {
+                    normalized.push(b'\n');
+                }
+                normalized
+            }
+            IgnoreSpace::Change => {
+                let mut normalized = Vec::with_capacity(line.len());
+                for word in line.split(u8::is_ascii_whitespace) {
+                    if word.is_empty() {
+                        // Runs of whitespace are all equal to a single space
+                        if normalized.last() != Some(&b' ') {
+                            normalized.push(b' ');
+                        }
+                        continue;
+                    }
+                    normalized.extend_from_slice(word);
+                    normalized.push(b' ');
+                }
+                // Whitespace at the end of the line doesn't count
+                if normalized.last() == Some(&b' ') {
+                    normalized.pop();
+                }
+                normalized
+            }
+            IgnoreSpace::All => line
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for IgnoreSpace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IgnoreSpace::None => write!(f, "not ignoring whitespace"),
+            IgnoreSpace::CrAtEol => write!(f, "ignoring CR at end of lines"),
+            IgnoreSpace::Change => write!(f, "ignoring changes in amount of whitespace"),
+            IgnoreSpace::All => write!(f, "ignoring all whitespace"),
+        }
+    }
+}
+
+/// The changed line ranges between the two texts, leaving out the changes that the options
+/// say to ignore
+pub(crate) fn line_changes(
+    prev_data: &[u8],
+    now_data: &[u8],
+    opts: &DiffOptions,
+) -> Vec<(Range<u32>, Range<u32>)> {
+    let mut changes = Vec::new();
+    let sink = |before: Range<u32>, after: Range<u32>| changes.push((before, after));
+    if opts.ignore_space == IgnoreSpace::None {
+        let input = InternedInput::new(prev_data, now_data);
+        gix::diff::blob::diff(opts.algorithm, &input, sink);
+    } else {
+        // The lines stay the same in number, so the ranges apply to the original lines
+        let prev_lines = byte_lines_with_terminator(prev_data);
+        let now_lines = byte_lines_with_terminator(now_data);
+        let mut input = InternedInput {
+            before: Vec::new(),
+            after: Vec::new(),
+            interner: Interner::new(prev_data.len() / 32 + now_data.len() / 32),
+        };
+        input.update_before(prev_lines.map(|line| opts.ignore_space.normalize(line)));
+        input.update_after(now_lines.map(|line| opts.ignore_space.normalize(line)));
+        gix::diff::blob::diff(opts.algorithm, &input, sink);
+    }
+    if opts.ignore_blank_lines {
+        let prev_lines = byte_lines_with_terminator(prev_data).collect::<Vec<_>>();
+        let now_lines = byte_lines_with_terminator(now_data).collect::<Vec<_>>();
+        let is_blank = |line: &&[u8]| line.iter().all(u8::is_ascii_whitespace);
+        changes.retain(|(before, after)| {
+            let removed = &prev_lines[before.start as usize..before.end as usize];
+            let added = &now_lines[after.start as usize..after.end as usize];
+            !(removed.iter().all(is_blank) && added.iter().all(is_blank))
+        });
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn change_ignores_the_amount_of_whitespace() {
+        let normalize = |line: &[u8]| IgnoreSpace::Change.normalize(line);
+        assert_eq!(normalize(b"a \t b\n"), b"a b");
+        // Leading whitespace counts as a single space, but differs from none
+        assert_eq!(normalize(b"   a b\n"), normalize(b"\ta b\n"));
+        assert_eq!(normalize(b"   a b\n"), b" a b");
+        assert_ne!(normalize(b" a b\n"), normalize(b"a b\n"));
+        // Trailing whitespace doesn't count at all, with or without a final newline
+        assert_eq!(normalize(b"a b  \n"), b"a b");
+        assert_eq!(normalize(b"a b \r\n"), b"a b");
+        assert_eq!(normalize(b"a b \t"), b"a b");
+        assert_eq!(normalize(b" \n"), b"");
+    }
+
+    #[test]
+    fn cr_at_eol_only_strips_a_final_cr() {
+        let normalize = |line: &[u8]| IgnoreSpace::CrAtEol.normalize(line);
+        assert_eq!(normalize(b"a\r\n"), b"a\n");
+        assert_eq!(normalize(b"a\n"), b"a\n");
+        // The last line may lack the newline
+        assert_eq!(normalize(b"a\r"), b"a");
+        assert_eq!(normalize(b"a"), b"a");
+        assert_eq!(normalize(b"a\r\r\n"), b"a\r\n");
+        assert_eq!(normalize(b"a\rb\n"), b"a\rb\n");
+    }
+
+    #[test]
+    fn ignored_changes_are_left_out() {
+        let opts = DiffOptions {
+            ignore_space: IgnoreSpace::Change,
+            ..DiffOptions::default()
+        };
+        let changes = line_changes(b"a  b\nc\n", b"a b\nd\n", &opts);
+        assert_eq!(changes, [(1..2, 1..2)]);
+    }
+}
@@ -0,0 +1,235 @@
+use gix::diff::blob::{
+    Algorithm,
+    intern::{InternedInput, Interner},
+};
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+
+/// Blocks of changed lines larger than this are shown without looking at their words
+const MAX_BLOCK_LINES: usize = 1000;
+
+/// How changes within lines are shown
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub(crate) enum WordDiff {
+    /// Only whole lines are marked
+    Lines,
+    /// Changed lines are shown with the changed words emphasised
+    #[default]
+    Highlight,
+    /// Removed and added words are shown inline, like `git diff --word-diff`
+    Words,
+}
+
+impl WordDiff {
+    pub(crate) fn next(self) -> WordDiff {
+        match self {
+            WordDiff::Lines => WordDiff::Highlight,
+            WordDiff::Highlight => WordDiff::Words,
+            WordDiff::Words => WordDiff::Lines,
+        }
+    }
+}
+
+impl std::fmt::Display for WordDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WordDiff::Lines => write!(f, "line diff"),
+            WordDiff::Highlight => write!(f, "line diff with changed words highlighted"),
+            WordDiff::Words => write!(f, "word diff"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TokenChange {
+    Same,
+    Removed,
+    Added,
+}
+
+/// Splits the text into words, runs of whitespace, and single other characters
+fn tokenize(text: &str) -> Vec<&str> {
+    let class = |c: char| {
+        if c.is_alphanumeric() || c == '_' {
+            0
+        } else if c.is_whitespace() && c != '\n' {
+            1
+        } else {
+            2
+        }
+    };
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((_idx, c)) = chars.next() {
+        let joins = chars
+            .peek()
+            .is_some_and(|(_idx, next)| class(c) != 2 && class(c) == class(*next));
+        if !joins {
+            let end = chars.peek().map_or(text.len(), |(idx, _next)| *idx);
+            tokens.push(&text[start..end]);
+            start = end;
+        }
+    }
+    tokens
+}
+
+/// Diffs the words of the removed lines against the ones of the added lines, in the order
+/// that a word diff shows them
+fn token_changes<'a>(
+    removed: &[&'a str],
+    added: &[&'a str],
+    algorithm: Algorithm,
+) -> Vec<(TokenChange, &'a str)> {
+    let tokens = |lines: &[&'a str]| {
+        lines
+            .iter()
+            .flat_map(|line| tokenize(line).into_iter().chain(["\n"]))
+            .collect::<Vec<_>>()
+    };
+    let before = tokens(removed);
+    let after = tokens(added);
+    let mut input = InternedInput {
+        before: Vec::new(),
+        after: Vec::new(),
+        interner: Interner::new(before.len() + after.len()),
+    };
+    input.update_before(before.iter().copied());
+    input.update_after(after.iter().copied());
+
+    let mut changes = Vec::new();
+    let (mut before_pos, mut after_pos) = (0, 0);
+    gix::diff::blob::diff(
+        algorithm,
+        &input,
+        |before_range: std::ops::Range<u32>, after_range: std::ops::Range<u32>| {
+            let (before_range, after_range) = (
+                before_range.start as usize..before_range.end as usize,
+                after_range.start as usize..after_range.end as usize,
+            );
+            changes.extend(
+                after[after_pos..after_range.start]
+                    .iter()
+                    .map(|t| (TokenChange::Same, *t)),
+            );
+            changes.extend(
+                before[before_range.clone()]
+                    .iter()
+                    .map(|t| (TokenChange::Removed, *t)),
+            );
+            changes.extend(
+                after[after_range.clone()]
+                    .iter()
+                    .map(|t| (TokenChange::Added, *t)),
+            );
+            before_pos = before_range.end;
+            after_pos = after_range.end;
+        },
+    );
+    debug_assert_eq!(before.len() - before_pos, after.len() - after_pos);
+    changes.extend(after[after_pos..].iter().map(|t| (TokenChange::Same, *t)));
+    changes
+}
+
+/// Whether the lines have anything but whitespace in common, otherwise emphasising the
+/// changed words would just emphasise everything
+fn shares_words(changes: &[(TokenChange, &str)]) -> bool {
+    changes
+        .iter()
+        .any(|(change, token)| *change == TokenChange::Same && !token.trim().is_empty())
+}
+
+/// Appends the token to the line, merging it with the last span if it has the same style
+fn push_token(spans: &mut Vec<Span<'static>>, token: &str, style: Style) {
+    match spans.last_mut() {
+        Some(last) if last.style == style => last.content.to_mut().push_str(token),
+        _ => spans.push(Span::styled(token.to_owned(), style)),
+    }
+}
+
+fn plain_lines(lines: &[&str], marker: &str, style: Style) -> Vec<Line<'static>> {
+    lines
+        .iter()
+        .map(|line| Line::from(format!("{marker}{line}")).style(style))
+        .collect()
+}
+
+/// Styles a block of removed lines followed by the lines that replaced them, emphasising
+/// the words that changed
+pub(crate) fn highlight_block(
+    removed: &[&str],
+    added: &[&str],
+    algorithm: Algorithm,
+) -> Vec<Line<'static>> {
+    let (removed_style, added_style) = (Style::default().red(), Style::default().green());
+    let plain = |lines: &mut Vec<Line<'static>>| {
+        lines.extend(plain_lines(removed, "-", removed_style));
+        lines.extend(plain_lines(added, "+", added_style));
+    };
+    let mut lines = Vec::new();
+    if removed.is_empty() || added.is_empty() || removed.len() + added.len() > MAX_BLOCK_LINES {
+        plain(&mut lines);
+        return lines;
+    }
+    let changes = token_changes(removed, added, algorithm);
+    if !shares_words(&changes) {
+        plain(&mut lines);
+        return lines;
+    }
+
+    let mut side_lines = |side: TokenChange, marker: &str, style: Style| {
+        let mut spans = vec![Span::styled(marker.to_owned(), style)];
+        for (change, token) in &changes {
+            if ![TokenChange::Same, side].contains(change) {
+                continue;
+            }
+            if *token == "\n" {
+                let spans =
+                    std::mem::replace(&mut spans, vec![Span::styled(marker.to_owned(), style)]);
+                lines.push(Line::from(spans).style(style));
+                continue;
+            }
+            let token_style = if *change == side {
+                style.reversed()
+            } else {
+                style
+            };
+            push_token(&mut spans, token, token_style);
+        }
+    };
+    side_lines(TokenChange::Removed, "-", removed_style);
+    side_lines(TokenChange::Added, "+", added_style);
+    lines
+}
+
+/// Shows a block of removed lines followed by the lines that replaced them as a word diff,
+/// with the removed and added words inline
+pub(crate) fn word_diff_block(
+    removed: &[&str],
+    added: &[&str],
+    algorithm: Algorithm,
+) -> Vec<Line<'static>> {
+    let (removed_style, added_style) = (Style::default().red(), Style::default().green());
+    if removed.len() + added.len() > MAX_BLOCK_LINES {
+        let mut lines = plain_lines(removed, "", removed_style);
+        lines.extend(plain_lines(added, "", added_style));
+        return lines;
+    }
+    let mut lines = Vec::new();
+    let mut spans = Vec::new();
+    for (change, token) in token_changes(removed, added, algorithm) {
+        if token == "\n" {
+            lines.push(Line::from(std::mem::take(&mut spans)));
+            continue;
+        }
+        let style = match change {
+            TokenChange::Same => Style::default(),
+            TokenChange::Removed => removed_style,
+            TokenChange::Added => added_style,
+        };
+        push_token(&mut spans, token, style);
+    }
+    lines
+}
@@ -0,0 +1,156 @@
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{Receiver, Sender},
+};
+
+use gix::Repository;
+
+use crate::{
+    model::{
+        Detail, DetailParams, Log, LogParams, LogWalk, ShallowId, compute_detail,
+        walk_commits_shallow,
+    },
+    rendered_diff::RenderedDiff,
+    syntax::Highlighter,
+};
+
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum JobResult {
+    Log {
+        generation: u64,
+        log: Result<Log, anyhow::Error>,
+    },
+    Detail {
+        generation: u64,
+        id: ShallowId,
+        detail: Detail,
+        rendered: RenderedDiff,
+    },
+}
+
+/// Threads that compute the log and the details of commits, and render the latter, so that
+/// the UI stays responsive
+pub(crate) struct Workers {
+    log: Sender<(u64, (LogParams, Option<LogWalk>))>,
+    detail: Sender<(u64, (Vec<ShallowId>, DetailParams))>,
+    results: Receiver<JobResult>,
+    /// Identifies a job, so that outdated results can be told apart from current ones
+    next_generation: u64,
+}
+
+impl Workers {
+    pub(crate) fn spawn(repo: &Repository) -> Workers {
+        let (result_sender, results) = std::sync::mpsc::channel();
+        let log = spawn_worker(
+            repo.clone(),
+            result_sender.clone(),
+            |repo, generation, (params, walk)| JobResult::Log {
+                generation,
+                log: walk_commits_shallow(repo, &params, walk),
+            },
+        );
+        let detail = spawn_detail_worker(repo.clone(), result_sender);
+        Workers {
+            log,
+            detail,
+            results,
+            next_generation: 0,
+        }
+    }
+    fn generation(&mut self) -> u64 {
+        self.next_generation += 1;
+        self.next_generation
+    }
+    /// Requests to continue the walk of the log, or to start a new one
+    pub(crate) fn request_log(&mut self, params: LogParams, walk: Option<LogWalk>) -> u64 {
+        let generation = self.generation();
+        // Sending only fails if the worker died, nothing to be done about that here
+        let _ = self.log.send((generation, (params, walk)));
+        generation
+    }
+    /// Requests the details of the given entries of the log, in the given order.
+    ///
+    /// Entries of earlier requests that haven't been computed yet are dropped. The results
+    /// carry the generation along, which the caller changes whenever the params do.
+    pub(crate) fn request_details(
+        &mut self,
+        ids: Vec<ShallowId>,
+        params: DetailParams,
+        generation: u64,
+    ) {
+        let _ = self.detail.send((generation, (ids, params)));
+    }
+    pub(crate) fn try_recv(&self) -> Option<JobResult> {
+        self.results.try_recv().ok()
+    }
+}
+
+fn spawn_worker<J: Send + 'static>(
+    repo: Repository,
+    results: Sender<JobResult>,
+    run: impl Fn(&Repository, u64, J) -> JobResult + Send + 'static,
+) -> Sender<(u64, J)> {
+    let (sender, receiver) = std::sync::mpsc::channel::<(u64, J)>();
+    std::thread::spawn(move || {
+        while let Ok(mut job) = receiver.recv() {
+            // Only the latest job is of interest, skip the ones that it superseded
+            while let Ok(newer_job) = receiver.try_recv() {
+                job = newer_job;
+            }
+            let (generation, job) = job;
+            if results.send(run(&repo, generation, job)).is_err() {
+                break;
+            }
+        }
+    });
+    sender
+}
+
+fn spawn_detail_worker(
+    repo: Repository,
+    results: Sender<JobResult>,
+) -> Sender<(u64, (Vec<ShallowId>, DetailParams))> {
+    let (sender, receiver) = std::sync::mpsc::channel::<(u64, (Vec<ShallowId>, DetailParams))>();
+    std::thread::spawn(move || {
+        let mut ids = VecDeque::new();
+        let mut params = None;
+        let mut generation = 0;
+        // Loading the grammars takes a moment, better before the first details are wanted
+        let highlighter = Highlighter::new();
+        loop {
+            let job = if ids.is_empty() {
+                match receiver.recv() {
+                    Ok(job) => Some(job),
+                    Err(_) => break,
+                }
+            } else {
+                receiver.try_recv().ok()
+            };
+            // A newer job supersedes what is left of the current one
+            if let Some(mut job) = job {
+                while let Ok(newer_job) = receiver.try_recv() {
+                    job = newer_job;
+                }
+                let (job_generation, (job_ids, job_params)) = job;
+                generation = job_generation;
+                ids = job_ids.into();
+                params = Some(job_params);
+            }
+            let (Some(id), Some(params)) = (ids.pop_front(), &params) else {
+                continue;
+            };
+            let detail = compute_detail(&repo, id, params);
+            let rendered = params.render_detail(&detail, &highlighter);
+            let result = JobResult::Detail {
+                generation,
+                id,
+                detail,
+                rendered,
+            };
+            if results.send(result).is_err() {
+                break;
+            }
+        }
+    });
+    sender
+}